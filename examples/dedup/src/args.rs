@@ -0,0 +1,14 @@
+use clap::Parser;
+
+/// Arguments for the example.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    /// Path to the directory of images, which is searched recursively
+    #[arg(short, long)]
+    pub directory: String,
+
+    /// Maximum distance between faces of duplicate images
+    #[arg(short, long, default_value_t = 0.2)]
+    pub threshold: f64,
+}
@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use dlib_face_recognition::*;
+
+mod args;
+
+use args::Args;
+
+fn tick<R>(name: &str, f: impl FnOnce() -> R) -> R {
+    let now = std::time::Instant::now();
+    let result = f();
+    println!("[{}] elapsed time: {}ms", name, now.elapsed().as_millis());
+    result
+}
+
+/// All files below a directory, in a stable order.
+fn walk(directory: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        println!("Unable to read directory {}", directory.display());
+        return;
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        if path.is_dir() {
+            walk(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let detector = FaceDetector::default();
+
+    let Ok(landmarks) = LandmarkPredictor::default() else {
+        panic!("Unable to load landmark predictor!");
+    };
+
+    let Ok(face_encoder) = FaceEncoderNetwork::default() else {
+        panic!("Unable to load face encoder!");
+    };
+
+    let mut files = Vec::new();
+    walk(Path::new(&args.directory), &mut files);
+
+    let deduplicator = Deduplicator {
+        threshold: args.threshold,
+        ..Default::default()
+    };
+
+    let mut paths = Vec::new();
+    let images = files.into_iter().filter_map(|path| {
+        // skip files that are not images
+        let image = image::open(&path).ok()?;
+        paths.push(path);
+        Some(ImageMatrix::from_image(&image.to_rgb8()))
+    });
+
+    let groups = tick("Grouping", || {
+        deduplicator.group_images(&detector, &landmarks, &face_encoder, images)
+    });
+    let Ok(groups) = groups else {
+        panic!("Faces were encoded by different models!");
+    };
+
+    println!(
+        "Found {} groups of duplicates among {} images",
+        groups.len(),
+        paths.len()
+    );
+
+    for group in groups {
+        println!("Keep {}", paths[group.canonical].display());
+
+        for &image in group
+            .images
+            .iter()
+            .filter(|&&image| image != group.canonical)
+        {
+            println!("    duplicate {}", paths[image].display());
+        }
+    }
+}
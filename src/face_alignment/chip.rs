@@ -0,0 +1,137 @@
+use std::fmt;
+
+use image::RgbImage;
+
+use super::details::ChipDetails;
+use crate::landmark_prediction::FaceLandmarks;
+use crate::matrix::ImageMatrix;
+
+/// An aligned crop of a single face, together with the transform it was extracted with.
+#[derive(Clone)]
+pub struct FaceChip {
+    image: ImageMatrix,
+    details: ChipDetails,
+}
+
+impl FaceChip {
+    /// The chip size used by the face encoding network.
+    pub const DEFAULT_SIZE: usize = 150;
+    /// The padding used by the face encoding network.
+    pub const DEFAULT_PADDING: f64 = 0.25;
+
+    /// Extract an aligned, `size` x `size` face chip from an image.
+    ///
+    /// `padding` is the amount of border added around the face, relative to the face size.
+    /// A padding of `0.0` results in a tightly cropped face, while `0.25` matches the chips
+    /// used by the face encoding network.
+    ///
+    /// The landmarks need to come from either the 5 or the 68 point landmark model.
+    pub fn extract(
+        image: &ImageMatrix,
+        landmarks: &FaceLandmarks,
+        size: usize,
+        padding: f64,
+    ) -> Result<Self, FaceChipError> {
+        if size == 0 {
+            return Err(FaceChipError::InvalidSize);
+        }
+        if padding.is_nan() || padding < 0.0 {
+            return Err(FaceChipError::InvalidPadding);
+        }
+        if !landmarks.is_alignable() {
+            return Err(FaceChipError::UnsupportedLandmarks(landmarks.len()));
+        }
+
+        let details = unsafe {
+            cpp!([landmarks as "const dlib::full_object_detection*", size as "size_t", padding as "double"] -> ChipDetails as "dlib::chip_details" {
+                return dlib::get_face_chip_details(*landmarks, size, padding);
+            })
+        };
+
+        let image = unsafe {
+            let details = &details;
+
+            cpp!([image as "const dlib::matrix<dlib::rgb_pixel>*", details as "const dlib::chip_details*"] -> ImageMatrix as "dlib::matrix<dlib::rgb_pixel>" {
+                dlib::matrix<dlib::rgb_pixel> chip;
+                dlib::extract_image_chip(*image, *details, chip);
+                return chip;
+            })
+        };
+
+        Ok(Self { image, details })
+    }
+
+    /// Extract one face chip per set of landmarks.
+    pub fn extract_all(
+        image: &ImageMatrix,
+        landmarks: &[FaceLandmarks],
+        size: usize,
+        padding: f64,
+    ) -> Result<Vec<Self>, FaceChipError> {
+        landmarks
+            .iter()
+            .map(|landmarks| Self::extract(image, landmarks, size, padding))
+            .collect()
+    }
+
+    /// The chip as a dlib matrix.
+    pub fn image(&self) -> &ImageMatrix {
+        &self.image
+    }
+
+    /// Copy the chip into an rgb image.
+    pub fn to_image(&self) -> RgbImage {
+        self.image.to_image()
+    }
+
+    /// Where the chip was cropped from in its source image.
+    pub fn details(&self) -> &ChipDetails {
+        &self.details
+    }
+
+    pub fn into_parts(self) -> (ImageMatrix, ChipDetails) {
+        (self.image, self.details)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FaceChipError {
+    /// The landmarks have a number of parts that dlib can't align, which is anything other than 5 or 68.
+    UnsupportedLandmarks(usize),
+    /// The requested chip size is zero.
+    InvalidSize,
+    /// The requested padding is negative or not a number.
+    InvalidPadding,
+}
+
+impl fmt::Display for FaceChipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedLandmarks(count) => write!(
+                f,
+                "Face chips can only be extracted from 5 or 68 landmarks, got {count}."
+            ),
+            Self::InvalidSize => write!(f, "Face chip size must be greater than zero."),
+            Self::InvalidPadding => write!(f, "Face chip padding must not be negative."),
+        }
+    }
+}
+
+impl std::error::Error for FaceChipError {}
+
+#[test]
+fn test_extract_from_default_landmarks() {
+    let image = ImageMatrix::default();
+    let landmarks = FaceLandmarks::default();
+
+    assert_eq!(
+        FaceChip::extract(
+            &image,
+            &landmarks,
+            FaceChip::DEFAULT_SIZE,
+            FaceChip::DEFAULT_PADDING
+        )
+        .err(),
+        Some(FaceChipError::UnsupportedLandmarks(0))
+    );
+}
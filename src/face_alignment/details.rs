@@ -0,0 +1,61 @@
+use super::transform::AffineTransform;
+use crate::geometry::Rectangle;
+
+cpp_class!(
+    /// A wrapper around the dlib `chip_details` class, which describes where a face chip
+    /// was cropped from in its source image.
+    /// https://github.com/davisking/dlib/blob/master/dlib/image_transforms/interpolation_abstract.h
+    pub unsafe struct ChipDetails as "dlib::chip_details"
+);
+
+impl ChipDetails {
+    /// The area of the source image the chip was cropped from, before rotation.
+    pub fn rect(&self) -> Rectangle {
+        unsafe {
+            cpp!([self as "const dlib::chip_details*"] -> Rectangle as "dlib::rectangle" {
+                return dlib::rectangle(self->rect);
+            })
+        }
+    }
+
+    /// The rotation of the chip, in radians, relative to the source image.
+    pub fn angle(&self) -> f64 {
+        unsafe {
+            cpp!([self as "const dlib::chip_details*"] -> f64 as "double" {
+                return self->angle;
+            })
+        }
+    }
+
+    /// The number of columns of the extracted chip.
+    pub fn width(&self) -> usize {
+        unsafe {
+            cpp!([self as "const dlib::chip_details*"] -> usize as "size_t" {
+                return self->cols;
+            })
+        }
+    }
+
+    /// The number of rows of the extracted chip.
+    pub fn height(&self) -> usize {
+        unsafe {
+            cpp!([self as "const dlib::chip_details*"] -> usize as "size_t" {
+                return self->rows;
+            })
+        }
+    }
+
+    /// The transform mapping coordinates in the source image to coordinates in the chip.
+    pub fn mapping_to_chip(&self) -> AffineTransform {
+        unsafe {
+            cpp!([self as "const dlib::chip_details*"] -> AffineTransform as "dlib::point_transform_affine" {
+                return dlib::get_mapping_to_chip(*self);
+            })
+        }
+    }
+
+    /// The transform mapping coordinates in the chip back to coordinates in the source image.
+    pub fn mapping_from_chip(&self) -> AffineTransform {
+        self.mapping_to_chip().inverse()
+    }
+}
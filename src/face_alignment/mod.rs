@@ -0,0 +1,9 @@
+//! Structs for extracting aligned face chips from images and face landmarks.
+
+mod chip;
+mod details;
+mod transform;
+
+pub use self::chip::{FaceChip, FaceChipError};
+pub use self::details::ChipDetails;
+pub use self::transform::AffineTransform;
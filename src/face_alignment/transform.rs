@@ -0,0 +1,153 @@
+use crate::geometry::{Point, Rectangle};
+use crate::landmark_prediction::FaceLandmarks;
+
+cpp_class!(
+    /// A wrapper around the dlib `point_transform_affine` class, an affine transform of 2D points.
+    ///
+    /// Used to map coordinates between a source image and the face chips extracted from it,
+    /// see [`ChipDetails::mapping_to_chip`](super::ChipDetails::mapping_to_chip) and
+    /// [`ChipDetails::mapping_from_chip`](super::ChipDetails::mapping_from_chip).
+    pub unsafe struct AffineTransform as "dlib::point_transform_affine"
+);
+
+impl AffineTransform {
+    /// Create a transform computing `m * p + b` for every point `p`.
+    pub fn new(m: [[f64; 2]; 2], b: [f64; 2]) -> Self {
+        let m = m.as_ptr() as *const f64;
+        let b = b.as_ptr();
+
+        unsafe {
+            cpp!([m as "const double*", b as "const double*"] -> AffineTransform as "dlib::point_transform_affine" {
+                dlib::matrix<double,2,2> matrix;
+                matrix = m[0], m[1],
+                         m[2], m[3];
+
+                return dlib::point_transform_affine(matrix, dlib::dpoint(b[0], b[1]));
+            })
+        }
+    }
+
+    /// The linear part of the transform, in row-major order.
+    pub fn m(&self) -> [[f64; 2]; 2] {
+        let mut m = [[0.0; 2]; 2];
+        let out = m.as_mut_ptr() as *mut f64;
+
+        unsafe {
+            cpp!([self as "const dlib::point_transform_affine*", out as "double*"] {
+                const dlib::matrix<double,2,2>& m = self->get_m();
+                out[0] = m(0, 0);
+                out[1] = m(0, 1);
+                out[2] = m(1, 0);
+                out[3] = m(1, 1);
+            })
+        }
+
+        m
+    }
+
+    /// The translation part of the transform.
+    pub fn b(&self) -> [f64; 2] {
+        let mut b = [0.0; 2];
+        let out = b.as_mut_ptr();
+
+        unsafe {
+            cpp!([self as "const dlib::point_transform_affine*", out as "double*"] {
+                out[0] = self->get_b().x();
+                out[1] = self->get_b().y();
+            })
+        }
+
+        b
+    }
+
+    /// The transform mapping points back to where they came from.
+    pub fn inverse(&self) -> Self {
+        unsafe {
+            cpp!([self as "const dlib::point_transform_affine*"] -> AffineTransform as "dlib::point_transform_affine" {
+                return dlib::inv(*self);
+            })
+        }
+    }
+
+    /// Transform a sub-pixel point, such as the output of another model.
+    pub fn transform_coordinates(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+        let mut out = [0.0; 2];
+        let ptr = out.as_mut_ptr();
+
+        unsafe {
+            cpp!([self as "const dlib::point_transform_affine*", x as "double", y as "double", ptr as "double*"] {
+                dlib::dpoint p = (*self)(dlib::dpoint(x, y));
+                ptr[0] = p.x();
+                ptr[1] = p.y();
+            })
+        }
+
+        out
+    }
+
+    /// Transform a point, rounding it to the nearest pixel.
+    pub fn transform_point(&self, point: &Point) -> Point {
+        unsafe {
+            cpp!([self as "const dlib::point_transform_affine*", point as "const dlib::point*"] -> Point as "dlib::point" {
+                return dlib::point((*self)(*point));
+            })
+        }
+    }
+
+    /// Transform a rectangle.
+    ///
+    /// As the transform may contain a rotation, this returns the bounding box of the transformed corners.
+    pub fn transform_rect(&self, rect: &Rectangle) -> Rectangle {
+        unsafe {
+            cpp!([self as "const dlib::point_transform_affine*", rect as "const dlib::rectangle*"] -> Rectangle as "dlib::rectangle" {
+                return transform_rect(*self, *rect);
+            })
+        }
+    }
+
+    /// Transform every part of a set of landmarks, as well as the face rectangle they belong to.
+    ///
+    /// Parts which are not present are left as is.
+    pub fn transform_landmarks(&self, landmarks: &FaceLandmarks) -> FaceLandmarks {
+        unsafe {
+            cpp!([self as "const dlib::point_transform_affine*", landmarks as "const dlib::full_object_detection*"] -> FaceLandmarks as "dlib::full_object_detection" {
+                dlib::full_object_detection out(*landmarks);
+
+                for (unsigned long i = 0; i < out.num_parts(); i++) {
+                    if (out.part(i) != dlib::OBJECT_PART_NOT_PRESENT) {
+                        out.part(i) = dlib::point((*self)(out.part(i)));
+                    }
+                }
+                out.get_rect() = transform_rect(*self, out.get_rect());
+
+                return out;
+            })
+        }
+    }
+}
+
+#[test]
+fn test_transform_inverse() {
+    let transform = AffineTransform::new([[0.0, -2.0], [2.0, 0.0]], [10.0, 20.0]);
+    let point = Point::new(3, 4);
+
+    let mapped = transform.transform_point(&point);
+    assert_eq!(mapped, Point::new(2, 26));
+    assert_eq!(transform.inverse().transform_point(&mapped), point);
+
+    let rect = Rectangle {
+        left: 0,
+        top: 0,
+        right: 2,
+        bottom: 1,
+    };
+    assert_eq!(
+        transform.transform_rect(&rect),
+        Rectangle {
+            left: 8,
+            top: 20,
+            right: 10,
+            bottom: 24,
+        }
+    );
+}
@@ -0,0 +1,5 @@
+//! Structs for telling a known set of people apart.
+
+mod svm;
+
+pub use self::svm::{SvmClassifier, SvmTrainer, TrainError};
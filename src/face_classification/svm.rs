@@ -0,0 +1,452 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::face_encoding::persistence::{self, PersistenceError, Reader};
+use crate::face_encoding::{FaceEncoding, FingerprintMismatch, ModelFingerprint};
+
+const MAGIC: &[u8; 4] = b"DFRS";
+const VERSION: u8 = 1;
+
+/// The number of values in an encoding.
+const LEN: usize = 128;
+
+/// Trains a [`SvmClassifier`] with dlib's multiclass linear support vector machine.
+///
+/// For a closed set of people with several photos each, a trained classifier tells them apart
+/// more accurately than comparing distances against a threshold.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SvmTrainer {
+    /// Trades a wider margin for fewer misclassified training encodings, higher values fit the
+    /// training encodings more closely. Has to be positive.
+    pub c: f64,
+    /// Training stops once the solution is this accurate. Has to be positive.
+    pub epsilon: f64,
+    /// Training stops after this many iterations, even if the solution is not yet accurate enough.
+    pub max_iterations: usize,
+    /// The number of threads used for training.
+    pub threads: usize,
+}
+
+impl Default for SvmTrainer {
+    fn default() -> Self {
+        Self {
+            c: 1.0,
+            epsilon: 0.001,
+            max_iterations: 10_000,
+            threads: 4,
+        }
+    }
+}
+
+impl SvmTrainer {
+    /// Train a classifier on encodings, where `labels[i]` names the person of `encodings[i]`.
+    pub fn train<L: AsRef<str>>(
+        &self,
+        encodings: &[FaceEncoding],
+        labels: &[L],
+    ) -> Result<SvmClassifier, TrainError> {
+        if encodings.len() != labels.len() {
+            return Err(TrainError::LabelCount {
+                encodings: encodings.len(),
+                labels: labels.len(),
+            });
+        }
+        if !(self.c > 0.0 && self.epsilon > 0.0) || self.threads == 0 {
+            return Err(TrainError::InvalidParameters);
+        }
+
+        let mut fingerprint = None;
+        for encoding in encodings {
+            FingerprintMismatch::check(fingerprint, encoding.fingerprint())?;
+            fingerprint = fingerprint.or(encoding.fingerprint());
+        }
+
+        // dlib assigns the weights by label, which are the indices of the sorted names
+        let names: Vec<String> = labels
+            .iter()
+            .map(|label| label.as_ref())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        if names.len() < 2 {
+            return Err(TrainError::TooFewClasses);
+        }
+        let indices: Vec<usize> = labels
+            .iter()
+            .map(|label| {
+                names
+                    .binary_search_by(|name| name.as_str().cmp(label.as_ref()))
+                    .unwrap()
+            })
+            .collect();
+
+        let mut weights = vec![0.0; names.len() * LEN];
+        let mut bias = vec![0.0; names.len()];
+
+        let trained = unsafe {
+            let encodings = encodings.as_ptr();
+            let labels = indices.as_ptr();
+            let len = indices.len();
+            let num_classes = names.len();
+            let weights = weights.as_mut_ptr();
+            let bias = bias.as_mut_ptr();
+            let c = self.c;
+            let epsilon = self.epsilon;
+            let max_iterations = self.max_iterations;
+            let threads = self.threads;
+
+            cpp!([
+                encodings as "const face_encoding*",
+                labels as "const size_t*",
+                len as "size_t",
+                num_classes as "size_t",
+                weights as "double*",
+                bias as "double*",
+                c as "double",
+                epsilon as "double",
+                max_iterations as "size_t",
+                threads as "size_t"
+            ] -> bool as "bool" {
+                typedef dlib::matrix<double,0,1> sample_type;
+
+                std::vector<sample_type> samples;
+                std::vector<unsigned long> sample_labels;
+                samples.reserve(len);
+                sample_labels.reserve(len);
+                for (size_t i = 0; i < len; i++) {
+                    samples.push_back(encodings[i].values);
+                    sample_labels.push_back(labels[i]);
+                }
+
+                try {
+                    dlib::svm_multiclass_linear_trainer<dlib::linear_kernel<sample_type>, unsigned long> trainer;
+                    trainer.set_c(c);
+                    trainer.set_epsilon(epsilon);
+                    trainer.set_max_iterations(max_iterations);
+                    trainer.set_num_threads(threads);
+
+                    const auto df = trainer.train(samples, sample_labels);
+
+                    for (size_t row = 0; row < df.labels.size(); row++) {
+                        const size_t label = df.labels[row];
+                        if (label >= num_classes) {
+                            return false;
+                        }
+
+                        for (long column = 0; column < df.weights.nc(); column++) {
+                            weights[label * 128 + column] = df.weights(row, column);
+                        }
+                        bias[label] = df.b(row);
+                    }
+                    return true;
+                } catch (const dlib::error& exception) {
+                    return false;
+                }
+            })
+        };
+
+        if !trained {
+            return Err(TrainError::InvalidParameters);
+        }
+
+        Ok(SvmClassifier {
+            labels: names,
+            weights,
+            bias,
+            fingerprint,
+        })
+    }
+}
+
+/// Tells a closed set of people apart, see [`SvmTrainer::train`].
+///
+/// Every face is assigned one of the trained labels, even if it belongs to somebody else,
+/// so the decision score should be checked for faces of unknown people.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SvmClassifierRepr"))]
+pub struct SvmClassifier {
+    labels: Vec<String>,
+    /// The weights of every label, one row of 128 values each.
+    weights: Vec<f64>,
+    bias: Vec<f64>,
+    /// The fingerprint of the model that generated the training encodings, if known.
+    fingerprint: Option<ModelFingerprint>,
+}
+
+/// The fields of a deserialized [`SvmClassifier`], before they are validated.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SvmClassifierRepr {
+    labels: Vec<String>,
+    weights: Vec<f64>,
+    bias: Vec<f64>,
+    fingerprint: Option<ModelFingerprint>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SvmClassifierRepr> for SvmClassifier {
+    type Error = PersistenceError;
+
+    fn try_from(repr: SvmClassifierRepr) -> Result<Self, Self::Error> {
+        let classifier = Self {
+            labels: repr.labels,
+            weights: repr.weights,
+            bias: repr.bias,
+            fingerprint: repr.fingerprint,
+        };
+        if !classifier.is_valid() {
+            return Err(PersistenceError::Corrupted);
+        }
+        Ok(classifier)
+    }
+}
+
+impl SvmClassifier {
+    /// The labels the classifier was trained on, in ascending order.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// The fingerprint of the model that generated the training encodings, if known.
+    pub fn fingerprint(&self) -> Option<ModelFingerprint> {
+        self.fingerprint
+    }
+
+    /// The most likely label of a face, along with its decision score.
+    ///
+    /// Faces encoded by a different model than the training encodings get no label, see [`SvmClassifier::try_predict`].
+    pub fn predict(&self, face: &FaceEncoding) -> Option<(&str, f64)> {
+        self.try_predict(face).ok().flatten()
+    }
+
+    /// Like [`SvmClassifier::predict`], but fails if the face was encoded by a different model than the training encodings.
+    pub fn try_predict(
+        &self,
+        face: &FaceEncoding,
+    ) -> Result<Option<(&str, f64)>, FingerprintMismatch> {
+        Ok(self.try_decision_scores(face)?.into_iter().next())
+    }
+
+    /// The decision score of every label for a face, starting with the highest score.
+    ///
+    /// Higher scores are more likely, and the scores of a face resembling none of the labels
+    /// are all low. Faces encoded by a different model than the training encodings get no scores,
+    /// see [`SvmClassifier::try_decision_scores`].
+    pub fn decision_scores(&self, face: &FaceEncoding) -> Vec<(&str, f64)> {
+        self.try_decision_scores(face).unwrap_or_default()
+    }
+
+    /// Like [`SvmClassifier::decision_scores`], but fails if the face was encoded by a different model than the training encodings.
+    pub fn try_decision_scores(
+        &self,
+        face: &FaceEncoding,
+    ) -> Result<Vec<(&str, f64)>, FingerprintMismatch> {
+        FingerprintMismatch::check(self.fingerprint, face.fingerprint())?;
+
+        let mut scores: Vec<_> = self
+            .labels
+            .iter()
+            .zip(self.weights.chunks_exact(LEN).zip(&self.bias))
+            .map(|(label, (weights, bias))| {
+                let score = weights
+                    .iter()
+                    .zip(face.as_ref())
+                    .map(|(weight, value)| weight * value)
+                    .sum::<f64>()
+                    - bias;
+                (label.as_str(), score)
+            })
+            .collect();
+        scores.sort_by(|(_, x), (_, y)| y.total_cmp(x));
+
+        Ok(scores)
+    }
+
+    /// Store the classifier in a file, replacing it atomically.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PersistenceError> {
+        persistence::write_atomic(path.as_ref(), &self.to_bytes())
+    }
+
+    /// Load a classifier stored with [`SvmClassifier::save`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PersistenceError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Layout (all integers and values little-endian): magic bytes `DFRS`, format version `1`,
+    /// the fingerprint (8 bytes, zero if unknown), the number of labels (4 bytes), the labels,
+    /// and the FNV-1a checksum of all preceding bytes (8 bytes).
+    ///
+    /// A label is stored as the length of its name (4 bytes), its name in UTF-8,
+    /// its bias and its 128 weights as `f64`.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        persistence::write_u64(
+            &mut bytes,
+            self.fingerprint
+                .map_or(0, |fingerprint| fingerprint.as_u64()),
+        );
+        bytes.extend_from_slice(&(self.labels.len() as u32).to_le_bytes());
+
+        for ((label, weights), bias) in self
+            .labels
+            .iter()
+            .zip(self.weights.chunks_exact(LEN))
+            .zip(&self.bias)
+        {
+            bytes.extend_from_slice(&(label.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(label.as_bytes());
+            for value in std::iter::once(bias).chain(weights) {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        let checksum = persistence::checksum(&bytes);
+        persistence::write_u64(&mut bytes, checksum);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, PersistenceError> {
+        if bytes.len() < 8 {
+            return Err(PersistenceError::Truncated);
+        }
+        let (content, stored) = bytes.split_at(bytes.len() - 8);
+
+        let mut reader = Reader(content);
+        persistence::check_header(&mut reader, MAGIC, VERSION)?;
+        if u64::from_le_bytes(stored.try_into().unwrap()) != persistence::checksum(content) {
+            return Err(PersistenceError::ChecksumMismatch);
+        }
+
+        let fingerprint = ModelFingerprint::from_u64(reader.u64()?);
+        let len = reader.u32()?;
+
+        let mut classifier = Self {
+            labels: Vec::new(),
+            weights: Vec::new(),
+            bias: Vec::new(),
+            fingerprint,
+        };
+        for _ in 0..len {
+            let label_len = reader.u32()? as usize;
+            let label = String::from_utf8(reader.take(label_len)?.to_vec())
+                .map_err(|_| PersistenceError::Corrupted)?;
+
+            let mut values = reader
+                .take((LEN + 1) * 8)?
+                .chunks_exact(8)
+                .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()));
+
+            classifier.labels.push(label);
+            classifier.bias.extend(values.next());
+            classifier.weights.extend(values);
+        }
+
+        if !reader.0.is_empty() || !classifier.is_valid() {
+            return Err(PersistenceError::Corrupted);
+        }
+
+        Ok(classifier)
+    }
+
+    /// Whether there are sorted, distinct labels, each with a bias and a row of weights.
+    fn is_valid(&self) -> bool {
+        !self.labels.is_empty()
+            && self.labels.windows(2).all(|pair| pair[0] < pair[1])
+            && self.weights.len() == self.labels.len() * LEN
+            && self.bias.len() == self.labels.len()
+    }
+}
+
+/// A [`SvmClassifier`] could not be trained.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrainError {
+    /// There is not exactly one label for every encoding.
+    LabelCount { encodings: usize, labels: usize },
+    /// The encodings belong to fewer than two people.
+    TooFewClasses,
+    /// The parameters of the trainer are out of range.
+    InvalidParameters,
+    /// The encodings were generated by different models.
+    FingerprintMismatch(FingerprintMismatch),
+}
+
+impl fmt::Display for TrainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::LabelCount { encodings, labels } => write!(
+                f,
+                "Expected one label for each of the {encodings} encodings, found {labels}."
+            ),
+            Self::TooFewClasses => write!(f, "At least two different labels are required."),
+            Self::InvalidParameters => write!(f, "Invalid parameters for training."),
+            Self::FingerprintMismatch(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for TrainError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::FingerprintMismatch(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<FingerprintMismatch> for TrainError {
+    fn from(error: FingerprintMismatch) -> Self {
+        Self::FingerprintMismatch(error)
+    }
+}
+
+#[test]
+fn svm_classifier_test() {
+    // the first label prefers positive values, the second one negative values
+    let classifier = SvmClassifier {
+        labels: vec!["a".to_string(), "b".to_string()],
+        weights: [vec![1.0; LEN], vec![-1.0; LEN]].concat(),
+        bias: vec![0.5, 0.0],
+        fingerprint: None,
+    };
+
+    let (label, score) = classifier
+        .predict(&FaceEncoding::new_from_scalar(0.1))
+        .unwrap();
+    assert_eq!(label, "a");
+    assert!((score - (12.8 - 0.5)).abs() < 1e-9);
+    assert_eq!(
+        classifier.decision_scores(&FaceEncoding::new_from_scalar(-0.1))[0].0,
+        "b"
+    );
+
+    // faces of other models get no label instead of panicking
+    let tagged = SvmClassifier {
+        fingerprint: ModelFingerprint::from_u64(1),
+        ..classifier.clone()
+    };
+    let face = FaceEncoding::new_from_scalar(0.1).with_fingerprint(ModelFingerprint::from_u64(2));
+    assert!(tagged.try_predict(&face).is_err());
+    assert_eq!(tagged.predict(&face), None);
+    assert!(tagged.decision_scores(&face).is_empty());
+
+    let bytes = classifier.to_bytes();
+    assert_eq!(SvmClassifier::from_bytes(&bytes).unwrap(), classifier);
+
+    let mut damaged = bytes.clone();
+    damaged[12] ^= 1;
+    assert!(matches!(
+        SvmClassifier::from_bytes(&damaged),
+        Err(PersistenceError::ChecksumMismatch)
+    ));
+    assert!(matches!(
+        SvmClassifier::from_bytes(&bytes[..20]),
+        Err(PersistenceError::ChecksumMismatch)
+    ));
+}
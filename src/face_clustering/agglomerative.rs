@@ -0,0 +1,214 @@
+use super::distances::DistanceMatrix;
+use crate::face_encoding::{FaceEncoding, FingerprintMismatch, Metric};
+
+/// Groups face encodings by repeatedly merging the two closest clusters.
+///
+/// The distance between two clusters is the mean distance between their encodings
+/// (average linkage), which is less prone to chaining than merging by the closest pair.
+/// Merging stops once the closest clusters are further apart than the threshold.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Agglomerative {
+    /// Clusters with at most this distance are merged, defaults to the threshold of the metric.
+    pub threshold: f64,
+    /// How the distance between encodings is measured.
+    pub metric: Metric,
+}
+
+impl Default for Agglomerative {
+    fn default() -> Self {
+        Self {
+            threshold: Metric::default().threshold(),
+            metric: Metric::default(),
+        }
+    }
+}
+
+impl Agglomerative {
+    /// Cluster the encodings, returning the label of every encoding.
+    ///
+    /// Labels range from `0` to the number of clusters, exclusively.
+    /// Fails if the encodings were generated by different models.
+    pub fn cluster(&self, encodings: &[FaceEncoding]) -> Result<Vec<usize>, FingerprintMismatch> {
+        Ok(self.dendrogram(encodings)?.cut(self.threshold))
+    }
+
+    /// Merge the encodings into a single cluster, recording every merge.
+    ///
+    /// The dendrogram can be cut at several distances without clustering the encodings again.
+    /// Fails if the encodings were generated by different models.
+    pub fn dendrogram(
+        &self,
+        encodings: &[FaceEncoding],
+    ) -> Result<Dendrogram, FingerprintMismatch> {
+        let distances = DistanceMatrix::new(encodings, self.metric)?;
+
+        Ok(Dendrogram::average_linkage(&distances))
+    }
+}
+
+/// Two clusters merged into one, see [`Dendrogram::merges`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Merge {
+    /// The id of the first merged cluster.
+    pub left: usize,
+    /// The id of the second merged cluster.
+    pub right: usize,
+    /// The mean distance between the encodings of both clusters.
+    pub distance: f64,
+    /// The number of encodings in the merged cluster.
+    pub size: usize,
+}
+
+/// The merges of a hierarchical clustering, see [`Agglomerative::dendrogram`].
+///
+/// Every encoding starts as a cluster of its own, whose id is its index. The cluster created by
+/// the `k`-th merge has the id `len + k`, where `len` is the number of encodings.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dendrogram {
+    len: usize,
+    merges: Vec<Merge>,
+}
+
+impl Dendrogram {
+    /// The merges, ordered by increasing distance.
+    pub fn merges(&self) -> &[Merge] {
+        &self.merges
+    }
+
+    /// The number of clustered encodings.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The label of every encoding after applying all merges up to `distance`.
+    ///
+    /// Labels range from `0` to the number of clusters, exclusively,
+    /// and are assigned in the order the encodings were given.
+    pub fn cut(&self, distance: f64) -> Vec<usize> {
+        let mut parents: Vec<_> = (0..self.len + self.merges.len()).collect();
+        for (k, merge) in self.merges.iter().enumerate() {
+            if merge.distance <= distance {
+                parents[merge.left] = self.len + k;
+                parents[merge.right] = self.len + k;
+            }
+        }
+
+        let mut labels = vec![usize::MAX; parents.len()];
+        let mut clusters = 0;
+
+        (0..self.len)
+            .map(|mut id| {
+                while parents[id] != id {
+                    id = parents[id];
+                }
+                if labels[id] == usize::MAX {
+                    labels[id] = clusters;
+                    clusters += 1;
+                }
+                labels[id]
+            })
+            .collect()
+    }
+
+    /// Builds the dendrogram with the nearest-neighbour chain algorithm,
+    /// which takes quadratic time as average linkage never decreases the distance between clusters.
+    pub(crate) fn average_linkage(distances: &DistanceMatrix) -> Self {
+        let len = distances.len();
+
+        // merged clusters take the slot of their left child
+        let mut slots: Vec<Vec<f64>> = (0..len)
+            .map(|i| (0..len).map(|j| distances.get(i, j)).collect())
+            .collect();
+        let mut sizes = vec![1; len];
+        let mut active = vec![true; len];
+
+        let mut merges = Vec::with_capacity(len.saturating_sub(1));
+        let mut chain: Vec<usize> = Vec::new();
+
+        for _ in 1..len {
+            if chain.is_empty() {
+                chain.push(active.iter().position(|&active| active).unwrap());
+            }
+
+            let (a, b, distance) = loop {
+                let a = chain[chain.len() - 1];
+                let previous = chain.len().checked_sub(2).map(|i| chain[i]);
+
+                // prefer the previous cluster on ties, so the chain always ends
+                let mut nearest = previous;
+                let mut nearest_distance = previous.map_or(f64::INFINITY, |p| slots[a][p]);
+                for c in (0..len).filter(|&c| active[c] && c != a) {
+                    if slots[a][c] < nearest_distance {
+                        nearest = Some(c);
+                        nearest_distance = slots[a][c];
+                    }
+                }
+
+                let b = nearest.unwrap();
+                if Some(b) == previous {
+                    chain.truncate(chain.len() - 2);
+                    break (a.min(b), a.max(b), nearest_distance);
+                }
+                chain.push(b);
+            };
+
+            for c in (0..len).filter(|&c| active[c] && c != a && c != b) {
+                let distance = (sizes[a] as f64 * slots[a][c] + sizes[b] as f64 * slots[b][c])
+                    / (sizes[a] + sizes[b]) as f64;
+                slots[a][c] = distance;
+                slots[c][a] = distance;
+            }
+            sizes[a] += sizes[b];
+            active[b] = false;
+
+            merges.push(Merge {
+                left: a,
+                right: b,
+                distance,
+                size: sizes[a],
+            });
+        }
+
+        // the chain finds merges out of order, so replace their slots with cluster ids afterwards
+        merges.sort_by(|x, y| x.distance.total_cmp(&y.distance));
+
+        let mut ids: Vec<_> = (0..len).collect();
+        for (k, merge) in merges.iter_mut().enumerate() {
+            let (left, right) = (ids[merge.left], ids[merge.right]);
+            ids[merge.left] = len + k;
+            merge.left = left;
+            merge.right = right;
+        }
+
+        Self { len, merges }
+    }
+}
+
+#[test]
+fn agglomerative_test() {
+    let points = [0.0_f64, 10.0, 0.1, 10.5, 0.4, 30.0];
+    let distances = DistanceMatrix::from_fn(points.len(), |i, j| (points[i] - points[j]).abs());
+
+    let dendrogram = Dendrogram::average_linkage(&distances);
+    assert_eq!(dendrogram.len(), 6);
+
+    let merges = dendrogram.merges();
+    assert_eq!(merges.len(), 5);
+    assert!(merges.windows(2).all(|w| w[0].distance <= w[1].distance));
+    assert_eq!((merges[0].left, merges[0].right, merges[0].size), (0, 2, 2));
+    // the mean distance between {0.0, 0.1} and {0.4}
+    assert!((merges[1].distance - 0.35).abs() < 1e-9);
+    assert_eq!((merges[1].left, merges[1].right, merges[1].size), (6, 4, 3));
+    assert_eq!(merges[4].size, 6);
+
+    assert_eq!(dendrogram.cut(0.5), vec![0, 1, 0, 1, 0, 2]);
+    assert_eq!(dendrogram.cut(0.0), vec![0, 1, 2, 3, 4, 5]);
+    assert_eq!(dendrogram.cut(f64::INFINITY), vec![0; 6]);
+}
@@ -0,0 +1,90 @@
+use super::distances::check_fingerprints;
+use crate::face_encoding::{FaceEncoding, FingerprintMismatch, Metric};
+
+/// Groups face encodings with dlib's chinese whispers graph clustering.
+///
+/// Every encoding is connected to all encodings within the threshold, after which labels
+/// spread through the graph. The number of clusters does not have to be known in advance.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChineseWhispers {
+    /// Encodings with at most this distance are connected, defaults to the threshold of the metric.
+    pub threshold: f64,
+    /// The number of times the labels are propagated through the graph.
+    pub iterations: usize,
+    /// How the distance between encodings is measured.
+    pub metric: Metric,
+    /// Seeds the order in which labels are propagated, so clustering the same encodings
+    /// always yields the same labels.
+    pub seed: u64,
+}
+
+impl Default for ChineseWhispers {
+    fn default() -> Self {
+        Self {
+            threshold: Metric::default().threshold(),
+            iterations: 100,
+            metric: Metric::default(),
+            seed: 0,
+        }
+    }
+}
+
+impl ChineseWhispers {
+    /// Cluster the encodings, returning the label of every encoding.
+    ///
+    /// Labels range from `0` to the number of clusters, exclusively.
+    /// Fails if the encodings were generated by different models.
+    pub fn cluster(&self, encodings: &[FaceEncoding]) -> Result<Vec<usize>, FingerprintMismatch> {
+        check_fingerprints(encodings)?;
+
+        // every encoding is connected to itself, so it receives a label even without neighbours
+        let mut edges = Vec::new();
+        for i in 0..encodings.len() {
+            edges.extend_from_slice(&[i, i]);
+            for j in i + 1..encodings.len() {
+                if encodings[i].distance_with(&encodings[j], self.metric) <= self.threshold {
+                    edges.extend_from_slice(&[i, j]);
+                }
+            }
+        }
+
+        let mut labels = vec![0usize; encodings.len()];
+
+        let num_edges = edges.len() / 2;
+        let edges = edges.as_ptr();
+        let len = labels.len();
+        let out = labels.as_mut_ptr();
+        let iterations = self.iterations;
+        let seed = self.seed;
+
+        unsafe {
+            cpp!([
+                edges as "const size_t*",
+                num_edges as "size_t",
+                len as "size_t",
+                out as "size_t*",
+                iterations as "size_t",
+                seed as "uint64_t"
+            ] {
+                std::vector<dlib::sample_pair> pairs;
+                pairs.reserve(num_edges);
+                for (size_t i = 0; i < num_edges; i++) {
+                    pairs.push_back(dlib::sample_pair(edges[2 * i], edges[2 * i + 1]));
+                }
+
+                dlib::rand rnd;
+                rnd.set_seed(std::to_string(seed));
+
+                std::vector<unsigned long> labels;
+                dlib::chinese_whispers(pairs, labels, iterations, rnd);
+
+                for (size_t i = 0; i < len && i < labels.size(); i++) {
+                    out[i] = labels[i];
+                }
+            })
+        }
+
+        Ok(labels)
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+
+use super::distances::DistanceMatrix;
+use crate::face_encoding::{FaceEncoding, FingerprintMismatch, Metric};
+
+/// Groups face encodings by density, leaving encodings in sparse regions unlabeled as noise.
+///
+/// An encoding with at least `min_points` encodings within `eps`, counting itself, is a core
+/// encoding. Clusters are grown from core encodings, so a chain of similar faces ends up in
+/// one cluster, while a stranger appearing once or twice is reported as noise instead of
+/// being forced into a cluster of their own.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dbscan {
+    /// Encodings with at most this distance are neighbours.
+    ///
+    /// Defaults to `0.5`, a little below the threshold of the metric, as clusters would
+    /// otherwise merge through chains of similar looking people.
+    pub eps: f64,
+    /// The number of neighbours a core encoding needs, counting itself.
+    pub min_points: usize,
+    /// How the distance between encodings is measured.
+    pub metric: Metric,
+}
+
+impl Default for Dbscan {
+    fn default() -> Self {
+        Self {
+            eps: 0.5,
+            min_points: 3,
+            metric: Metric::default(),
+        }
+    }
+}
+
+impl Dbscan {
+    /// Cluster the encodings, returning the label of every encoding, or `None` for noise.
+    ///
+    /// Labels range from `0` to the number of clusters, exclusively.
+    /// Fails if the encodings were generated by different models.
+    pub fn cluster(
+        &self,
+        encodings: &[FaceEncoding],
+    ) -> Result<Vec<Option<usize>>, FingerprintMismatch> {
+        let distances = DistanceMatrix::new(encodings, self.metric)?;
+
+        Ok(self.cluster_distances(&distances))
+    }
+
+    pub(crate) fn cluster_distances(&self, distances: &DistanceMatrix) -> Vec<Option<usize>> {
+        let len = distances.len();
+        let neighbours = |i: usize| {
+            (0..len)
+                .filter(|&j| distances.get(i, j) <= self.eps)
+                .collect::<Vec<_>>()
+        };
+
+        let mut labels = vec![None; len];
+        let mut visited = vec![false; len];
+        let mut clusters = 0;
+
+        for start in 0..len {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+
+            let start_neighbours = neighbours(start);
+            if start_neighbours.len() < self.min_points {
+                // may still be claimed as a border encoding of a later cluster
+                continue;
+            }
+
+            let label = clusters;
+            clusters += 1;
+            labels[start] = Some(label);
+
+            let mut queue = VecDeque::from(start_neighbours);
+            while let Some(i) = queue.pop_front() {
+                if labels[i].is_none() {
+                    labels[i] = Some(label);
+                }
+                if visited[i] {
+                    continue;
+                }
+                visited[i] = true;
+
+                let next = neighbours(i);
+                if next.len() >= self.min_points {
+                    queue.extend(next.into_iter().filter(|&j| !visited[j]));
+                }
+            }
+        }
+
+        labels
+    }
+}
+
+#[test]
+fn dbscan_test() {
+    let points = [0.0_f64, 0.1, 0.2, 0.3, 5.0, 5.1, 5.2, 9.0];
+    let distances = DistanceMatrix::from_fn(points.len(), |i, j| (points[i] - points[j]).abs());
+
+    let dbscan = Dbscan {
+        eps: 0.15,
+        min_points: 3,
+        ..Default::default()
+    };
+    assert_eq!(
+        dbscan.cluster_distances(&distances),
+        vec![
+            Some(0),
+            Some(0),
+            Some(0),
+            Some(0),
+            Some(1),
+            Some(1),
+            Some(1),
+            None
+        ],
+    );
+
+    // without enough neighbours, every encoding is noise
+    let dbscan = Dbscan {
+        min_points: 4,
+        ..dbscan
+    };
+    assert_eq!(
+        dbscan.cluster_distances(&distances),
+        vec![None; points.len()]
+    );
+}
@@ -0,0 +1,166 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use super::distances::check_fingerprints;
+use crate::face_detection::FaceDetectorTrait;
+use crate::face_encoding::{FaceEncoderTrait, FaceEncoding, FingerprintMismatch, Metric};
+use crate::landmark_prediction::LandmarkPredictorTrait;
+use crate::matrix::ImageMatrix;
+
+/// Images showing the same face, see [`Deduplicator::group_images`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DuplicateGroup {
+    /// The indices of the images, in ascending order.
+    pub images: Vec<usize>,
+    /// The index of the image to keep, which is linked to the most other images of the group.
+    pub canonical: usize,
+}
+
+/// Finds images that were uploaded several times, e.g. the same ID photo re-encoded or resized.
+///
+/// Unlike clustering by identity, only faces that are near-duplicates are linked,
+/// so different photos of the same person stay apart.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Deduplicator {
+    /// Faces with at most this distance are near-duplicates.
+    ///
+    /// Defaults to `0.2`, far below the threshold of the metric, as copies of the same photo
+    /// typically lie within `0.1` while different photos of the same person lie around `0.3` to `0.5`.
+    pub threshold: f64,
+    /// How the distance between faces is measured.
+    pub metric: Metric,
+}
+
+impl Default for Deduplicator {
+    fn default() -> Self {
+        Self {
+            threshold: 0.2,
+            metric: Metric::default(),
+        }
+    }
+}
+
+impl Deduplicator {
+    /// Group the images whose faces are near-duplicates, detecting and encoding all faces of every image.
+    ///
+    /// The images are encoded one at a time, so they can be loaded while iterating,
+    /// e.g. while walking a directory. See [`Deduplicator::group`] for how images are grouped.
+    pub fn group_images<I: Borrow<ImageMatrix>>(
+        &self,
+        detector: &impl FaceDetectorTrait,
+        predictor: &impl LandmarkPredictorTrait,
+        encoder: &impl FaceEncoderTrait,
+        images: impl IntoIterator<Item = I>,
+    ) -> Result<Vec<DuplicateGroup>, FingerprintMismatch> {
+        let encodings: Vec<_> = images
+            .into_iter()
+            .map(|image| {
+                let image = image.borrow();
+                let locations = detector.face_locations(image);
+                let landmarks = predictor.face_landmarks_batch(image, &locations);
+
+                encoder.get_face_encodings(image, &landmarks, 0)
+            })
+            .collect();
+
+        self.group(&encodings)
+    }
+
+    /// Group the images whose faces are near-duplicates, given the encodings of all faces of every image.
+    ///
+    /// Two images are linked if any of their faces are near-duplicates, and groups contain all
+    /// images linked to each other, directly or through other images. Only groups of at least
+    /// two images are returned, ordered by their first image. Images without faces are never grouped.
+    ///
+    /// Fails if the encodings were generated by different models.
+    pub fn group<F: Deref<Target = [FaceEncoding]>>(
+        &self,
+        images: &[F],
+    ) -> Result<Vec<DuplicateGroup>, FingerprintMismatch> {
+        check_fingerprints(images.iter().flat_map(|faces| faces.iter()))?;
+
+        let faces: Vec<_> = images
+            .iter()
+            .enumerate()
+            .flat_map(|(image, faces)| faces.iter().map(move |face| (image, face)))
+            .collect();
+
+        // the closest distance between the faces of every pair of linked images
+        let mut links: HashMap<(usize, usize), f64> = HashMap::new();
+        let mut parents: Vec<_> = (0..images.len()).collect();
+
+        for (i, &(a, x)) in faces.iter().enumerate() {
+            for &(b, y) in &faces[i + 1..] {
+                if a == b {
+                    continue;
+                }
+
+                let distance = self.metric.distance(x.as_ref(), y.as_ref());
+                if distance <= self.threshold {
+                    let link = links.entry((a, b)).or_insert(f64::INFINITY);
+                    *link = link.min(distance);
+
+                    let (a, b) = (root(&mut parents, a), root(&mut parents, b));
+                    parents[a.max(b)] = a.min(b);
+                }
+            }
+        }
+
+        let mut groups: Vec<DuplicateGroup> = Vec::new();
+        let mut group_of_root = HashMap::new();
+        for image in 0..images.len() {
+            let index = *group_of_root
+                .entry(root(&mut parents, image))
+                .or_insert_with(|| {
+                    groups.push(DuplicateGroup {
+                        images: Vec::new(),
+                        canonical: image,
+                    });
+                    groups.len() - 1
+                });
+            groups[index].images.push(image);
+        }
+        groups.retain(|group| group.images.len() > 1);
+
+        // prefer the image linked to the most others, then the closest one, then the first one
+        let mut scores: HashMap<usize, (usize, f64)> = HashMap::new();
+        for (&(a, b), &distance) in &links {
+            for image in [a, b] {
+                let score = scores.entry(image).or_default();
+                score.0 += 1;
+                score.1 += distance;
+            }
+        }
+        for group in &mut groups {
+            group.canonical = group
+                .images
+                .iter()
+                .copied()
+                .min_by(|x, y| {
+                    let (x_links, x_distance) = scores[x];
+                    let (y_links, y_distance) = scores[y];
+                    y_links
+                        .cmp(&x_links)
+                        .then(
+                            (x_distance / x_links as f64).total_cmp(&(y_distance / y_links as f64)),
+                        )
+                        .then(x.cmp(y))
+                })
+                .unwrap();
+        }
+
+        Ok(groups)
+    }
+}
+
+/// The representative of the set containing `index`, halving the paths along the way.
+fn root(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
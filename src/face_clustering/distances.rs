@@ -0,0 +1,71 @@
+use crate::face_encoding::{FaceEncoding, FingerprintMismatch, Metric};
+
+/// The distances between all pairs of encodings.
+pub(crate) struct DistanceMatrix {
+    len: usize,
+    /// The upper triangle without the diagonal, row by row.
+    values: Vec<f64>,
+}
+
+impl DistanceMatrix {
+    /// Fails if the encodings were generated by different models.
+    pub(crate) fn new(
+        encodings: &[FaceEncoding],
+        metric: Metric,
+    ) -> Result<Self, FingerprintMismatch> {
+        check_fingerprints(encodings)?;
+
+        Ok(Self::from_fn(encodings.len(), |i, j| {
+            metric.distance(encodings[i].as_ref(), encodings[j].as_ref())
+        }))
+    }
+
+    pub(crate) fn from_fn(len: usize, distance: impl Fn(usize, usize) -> f64) -> Self {
+        let mut values = Vec::with_capacity(len * len.saturating_sub(1) / 2);
+        for i in 0..len {
+            for j in i + 1..len {
+                values.push(distance(i, j));
+            }
+        }
+
+        Self { len, values }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn get(&self, i: usize, j: usize) -> f64 {
+        let (i, j) = match i.cmp(&j) {
+            std::cmp::Ordering::Less => (i, j),
+            std::cmp::Ordering::Greater => (j, i),
+            std::cmp::Ordering::Equal => return 0.0,
+        };
+
+        self.values[i * (2 * self.len - i - 1) / 2 + j - i - 1]
+    }
+}
+
+/// Fails if the encodings were generated by different models.
+pub(crate) fn check_fingerprints<'a>(
+    encodings: impl IntoIterator<Item = &'a FaceEncoding>,
+) -> Result<(), FingerprintMismatch> {
+    let mut fingerprint = None;
+    for encoding in encodings {
+        FingerprintMismatch::check(fingerprint, encoding.fingerprint())?;
+        fingerprint = fingerprint.or(encoding.fingerprint());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn distance_matrix_test() {
+    let matrix = DistanceMatrix::from_fn(4, |i, j| (i * 10 + j) as f64);
+
+    assert_eq!(matrix.len(), 4);
+    assert_eq!(matrix.get(0, 1), 1.0);
+    assert_eq!(matrix.get(3, 1), 13.0);
+    assert_eq!(matrix.get(2, 3), 23.0);
+    assert_eq!(matrix.get(2, 2), 0.0);
+}
@@ -0,0 +1,14 @@
+//! Structs for grouping face encodings of unknown people.
+
+mod agglomerative;
+mod chinese_whispers;
+mod dbscan;
+mod dedup;
+mod distances;
+mod quality;
+
+pub use self::agglomerative::{Agglomerative, Dendrogram, Merge};
+pub use self::chinese_whispers::ChineseWhispers;
+pub use self::dbscan::Dbscan;
+pub use self::dedup::{Deduplicator, DuplicateGroup};
+pub use self::quality::ClusterQuality;
@@ -0,0 +1,127 @@
+use super::distances::DistanceMatrix;
+use crate::face_encoding::{FaceEncoding, FingerprintMismatch, Metric};
+
+/// Measures how well a clustering separates the encodings, e.g. to choose a threshold.
+///
+/// Accepts the labels of any clustering, where encodings labeled `None`, such as the noise
+/// of [`Dbscan`](super::Dbscan), are left out.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClusterQuality {
+    /// The mean silhouette of the labeled encodings, from `-1` to `1`, where higher is better.
+    ///
+    /// The silhouette of an encoding compares its mean distance to its own cluster
+    /// against its mean distance to the closest other cluster. Encodings alone in their
+    /// cluster have a silhouette of `0`. `None` if there are fewer than two clusters.
+    pub silhouette: Option<f64>,
+    /// The mean distance between encodings of the same cluster, or `None` if all clusters are singletons.
+    pub mean_intra_distance: Option<f64>,
+    /// The mean distance between encodings of different clusters, or `None` if there are fewer than two clusters.
+    pub mean_inter_distance: Option<f64>,
+}
+
+impl ClusterQuality {
+    /// Evaluate the labels of the encodings, as returned by any of the clustering methods.
+    ///
+    /// Fails if the encodings were generated by different models.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not exactly one label for every encoding.
+    pub fn evaluate<L: Copy + Into<Option<usize>>>(
+        encodings: &[FaceEncoding],
+        labels: &[L],
+        metric: Metric,
+    ) -> Result<Self, FingerprintMismatch> {
+        assert_eq!(
+            encodings.len(),
+            labels.len(),
+            "expected one label for every encoding"
+        );
+
+        let distances = DistanceMatrix::new(encodings, metric)?;
+        let labels: Vec<_> = labels.iter().map(|&label| label.into()).collect();
+
+        Ok(Self::from_distances(&distances, &labels))
+    }
+
+    pub(crate) fn from_distances(distances: &DistanceMatrix, labels: &[Option<usize>]) -> Self {
+        let clusters = labels.iter().flatten().max().map_or(0, |max| max + 1);
+        let mut sizes = vec![0usize; clusters];
+        for &label in labels.iter().flatten() {
+            sizes[label] += 1;
+        }
+        let non_empty = sizes.iter().filter(|&&size| size > 0).count();
+
+        let (mut intra, mut intra_count) = (0.0, 0usize);
+        let (mut inter, mut inter_count) = (0.0, 0usize);
+        let mut silhouettes = Vec::new();
+
+        for (i, label) in labels.iter().enumerate() {
+            let Some(label) = *label else { continue };
+
+            // the summed distance from this encoding to every cluster
+            let mut sums = vec![0.0; clusters];
+            for (j, other) in labels.iter().enumerate() {
+                let Some(other) = *other else { continue };
+                if i == j {
+                    continue;
+                }
+
+                let distance = distances.get(i, j);
+                sums[other] += distance;
+                if i < j {
+                    if label == other {
+                        intra += distance;
+                        intra_count += 1;
+                    } else {
+                        inter += distance;
+                        inter_count += 1;
+                    }
+                }
+            }
+
+            if sizes[label] > 1 {
+                let a = sums[label] / (sizes[label] - 1) as f64;
+                let b = (0..clusters)
+                    .filter(|&other| other != label && sizes[other] > 0)
+                    .map(|other| sums[other] / sizes[other] as f64)
+                    .fold(f64::INFINITY, f64::min);
+                silhouettes.push(if a.max(b) > 0.0 {
+                    (b - a) / a.max(b)
+                } else {
+                    0.0
+                });
+            } else {
+                silhouettes.push(0.0);
+            }
+        }
+
+        let mean = |sum: f64, count: usize| (count > 0).then(|| sum / count as f64);
+
+        Self {
+            silhouette: (non_empty >= 2)
+                .then(|| silhouettes.iter().sum::<f64>() / silhouettes.len() as f64),
+            mean_intra_distance: mean(intra, intra_count),
+            mean_inter_distance: mean(inter, inter_count),
+        }
+    }
+}
+
+#[test]
+fn cluster_quality_test() {
+    let points = [0.0_f64, 1.0, 10.0, 11.0, 50.0];
+    let distances = DistanceMatrix::from_fn(points.len(), |i, j| (points[i] - points[j]).abs());
+
+    let quality =
+        ClusterQuality::from_distances(&distances, &[Some(0), Some(0), Some(1), Some(1), None]);
+    assert_eq!(quality.mean_intra_distance, Some(1.0));
+    assert_eq!(quality.mean_inter_distance, Some(10.0));
+    // a = 1 and b = 10.5 or 9.5 for each encoding
+    let expected = (9.5 / 10.5 + 8.5 / 9.5 + 8.5 / 9.5 + 9.5 / 10.5) / 4.0;
+    assert!((quality.silhouette.unwrap() - expected).abs() < 1e-9);
+
+    let quality = ClusterQuality::from_distances(&distances, &[Some(0); 5]);
+    assert_eq!(quality.silhouette, None);
+    assert_eq!(quality.mean_inter_distance, None);
+}
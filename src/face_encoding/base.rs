@@ -6,6 +6,7 @@ pub trait FaceEncoderTrait {
     /// Get a number of face encodings from an image and a list of landmarks, and jitter them a certain amount.
     ///
     /// It is recommended to keep `num_jitters` at 0 unless you know what you're doing.
+    /// How the faces are jittered is controlled by the [`EncodingOptions`](crate::EncodingOptions) of the encoder.
     fn get_face_encodings(
         &self,
         image: &ImageMatrix,
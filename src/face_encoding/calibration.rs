@@ -0,0 +1,254 @@
+use std::fmt;
+
+use super::encoding::FaceEncoding;
+use super::fingerprint::FingerprintMismatch;
+use super::metric::Metric;
+
+/// How often a threshold accepts different people and rejects the same person.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErrorRates {
+    /// Pairs are accepted up to this distance.
+    pub threshold: f64,
+    /// The fraction of pairs of different people that are accepted.
+    pub false_accept_rate: f64,
+    /// The fraction of pairs of the same person that are rejected.
+    pub false_reject_rate: f64,
+}
+
+/// The distances of labeled pairs of encodings, used to choose a threshold for a metric
+/// that fits the people and cameras of an application better than [`Metric::threshold`].
+///
+/// The recommended threshold can be passed to [`FaceComparer::with_tolerance`](super::FaceComparer::with_tolerance)
+/// or [`Gallery::with_tolerance`](super::Gallery::with_tolerance).
+#[derive(Clone, Debug)]
+pub struct Calibration {
+    metric: Metric,
+    /// The distances of pairs of the same person, sorted ascending.
+    genuine: Vec<f64>,
+    /// The distances of pairs of different people, sorted ascending.
+    impostor: Vec<f64>,
+}
+
+impl Calibration {
+    /// Measure the distance of every pair, labeled `true` if both encodings belong to the same person.
+    pub fn new<'a>(
+        pairs: impl IntoIterator<Item = (&'a FaceEncoding, &'a FaceEncoding, bool)>,
+        metric: Metric,
+    ) -> Result<Self, CalibrationError> {
+        let mut fingerprint = None;
+        let mut genuine = Vec::new();
+        let mut impostor = Vec::new();
+
+        for (a, b, same) in pairs {
+            for encoding in [a, b] {
+                FingerprintMismatch::check(fingerprint, encoding.fingerprint())?;
+                fingerprint = fingerprint.or(encoding.fingerprint());
+            }
+
+            let distance = metric.distance(a.as_ref(), b.as_ref());
+            if same {
+                genuine.push(distance);
+            } else {
+                impostor.push(distance);
+            }
+        }
+
+        Self::from_distances(genuine, impostor, metric)
+    }
+
+    /// Use distances that were already measured with the metric.
+    pub fn from_distances(
+        mut genuine: Vec<f64>,
+        mut impostor: Vec<f64>,
+        metric: Metric,
+    ) -> Result<Self, CalibrationError> {
+        if genuine.is_empty() {
+            return Err(CalibrationError::NoGenuinePairs);
+        }
+        if impostor.is_empty() {
+            return Err(CalibrationError::NoImpostorPairs);
+        }
+        if !genuine
+            .iter()
+            .chain(&impostor)
+            .all(|distance| distance.is_finite())
+        {
+            return Err(CalibrationError::NonFiniteDistance);
+        }
+
+        genuine.sort_unstable_by(f64::total_cmp);
+        impostor.sort_unstable_by(f64::total_cmp);
+
+        Ok(Self {
+            metric,
+            genuine,
+            impostor,
+        })
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    /// The number of pairs of the same person.
+    pub fn genuine_pairs(&self) -> usize {
+        self.genuine.len()
+    }
+
+    /// The number of pairs of different people.
+    pub fn impostor_pairs(&self) -> usize {
+        self.impostor.len()
+    }
+
+    /// The error rates when accepting pairs up to the threshold.
+    pub fn rates(&self, threshold: f64) -> ErrorRates {
+        let accepted = |distances: &[f64]| {
+            distances.partition_point(|&distance| distance <= threshold) as f64
+                / distances.len() as f64
+        };
+
+        ErrorRates {
+            threshold,
+            false_accept_rate: accepted(&self.impostor),
+            false_reject_rate: 1.0 - accepted(&self.genuine),
+        }
+    }
+
+    /// The error rates at every distinct distance of a pair, sorted by ascending threshold.
+    ///
+    /// The false accept rate grows and the false reject rate shrinks along the curve.
+    pub fn roc(&self) -> Vec<ErrorRates> {
+        let mut thresholds: Vec<_> = self.genuine.iter().chain(&self.impostor).copied().collect();
+        thresholds.sort_unstable_by(f64::total_cmp);
+        thresholds.dedup();
+
+        thresholds
+            .into_iter()
+            .map(|threshold| self.rates(threshold))
+            .collect()
+    }
+
+    /// The threshold where both error rates are closest to each other, and the mean of both rates there.
+    pub fn equal_error_rate(&self) -> (f64, f64) {
+        let rates = self
+            .roc()
+            .into_iter()
+            .min_by(|a, b| {
+                let gap =
+                    |rates: &ErrorRates| (rates.false_accept_rate - rates.false_reject_rate).abs();
+                gap(a).total_cmp(&gap(b))
+            })
+            .expect("both kinds of pairs are present");
+
+        (
+            rates.threshold,
+            (rates.false_accept_rate + rates.false_reject_rate) / 2.0,
+        )
+    }
+
+    /// The largest threshold whose false accept rate is at most the target,
+    /// which rejects the fewest pairs of the same person.
+    ///
+    /// Returns `None` if even the smallest distance accepts too many pairs of different people.
+    /// The estimate gets unreliable for targets close to `1 / impostor_pairs()`, so gather many
+    /// more pairs of different people than the target demands.
+    pub fn threshold_for_far(&self, target: f64) -> Option<f64> {
+        self.roc()
+            .into_iter()
+            .rev()
+            .find(|rates| rates.false_accept_rate <= target)
+            .map(|rates| rates.threshold)
+    }
+}
+
+/// Why a [`Calibration`] could not be created.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CalibrationError {
+    /// None of the pairs belong to the same person.
+    NoGenuinePairs,
+    /// None of the pairs belong to different people.
+    NoImpostorPairs,
+    /// A distance is NaN or infinite, e.g. because an encoding contains NaN.
+    NonFiniteDistance,
+    /// The encodings were generated by different models.
+    FingerprintMismatch(FingerprintMismatch),
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoGenuinePairs => write!(f, "No pairs of the same person to calibrate with."),
+            Self::NoImpostorPairs => write!(f, "No pairs of different people to calibrate with."),
+            Self::NonFiniteDistance => {
+                write!(f, "Cannot calibrate with NaN or infinite distances.")
+            }
+            Self::FingerprintMismatch(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for CalibrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::FingerprintMismatch(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<FingerprintMismatch> for CalibrationError {
+    fn from(error: FingerprintMismatch) -> Self {
+        Self::FingerprintMismatch(error)
+    }
+}
+
+#[test]
+fn calibration_test() {
+    let calibration = Calibration::from_distances(
+        vec![0.3, 0.1, 0.4, 0.2, 0.7],
+        vec![0.9, 0.5, 0.8, 1.0, 0.6],
+        Metric::Euclidean,
+    )
+    .unwrap();
+    assert_eq!(calibration.genuine_pairs(), 5);
+    assert_eq!(calibration.impostor_pairs(), 5);
+
+    let rates = calibration.rates(0.55);
+    assert_eq!(rates.false_accept_rate, 0.2);
+    assert!((rates.false_reject_rate - 0.2).abs() < 1e-12);
+    assert_eq!(calibration.rates(0.0).false_reject_rate, 1.0);
+    assert_eq!(calibration.rates(1.0).false_accept_rate, 1.0);
+
+    let roc = calibration.roc();
+    assert_eq!(roc.len(), 10);
+    assert!(roc.windows(2).all(|pair| {
+        pair[0].threshold < pair[1].threshold
+            && pair[0].false_accept_rate <= pair[1].false_accept_rate
+            && pair[0].false_reject_rate >= pair[1].false_reject_rate
+    }));
+
+    let (threshold, rate) = calibration.equal_error_rate();
+    assert_eq!(threshold, 0.5);
+    assert!((rate - 0.2).abs() < 1e-12);
+
+    assert_eq!(calibration.threshold_for_far(0.0), Some(0.4));
+    assert_eq!(calibration.threshold_for_far(0.2), Some(0.5));
+    assert_eq!(calibration.threshold_for_far(1.0), Some(1.0));
+
+    let separated =
+        Calibration::from_distances(vec![0.2], vec![0.1, 0.3], Metric::Euclidean).unwrap();
+    assert_eq!(separated.threshold_for_far(0.0), None);
+
+    assert_eq!(
+        Calibration::from_distances(vec![], vec![0.5], Metric::Euclidean).unwrap_err(),
+        CalibrationError::NoGenuinePairs,
+    );
+    for (genuine, impostor) in [(f64::NAN, 0.5), (0.2, f64::INFINITY)] {
+        assert_eq!(
+            Calibration::from_distances(vec![0.1, genuine], vec![impostor], Metric::Euclidean)
+                .unwrap_err(),
+            CalibrationError::NonFiniteDistance,
+        );
+    }
+}
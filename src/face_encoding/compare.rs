@@ -1,41 +1,250 @@
 use std::collections::HashMap;
 
 use super::encoding::FaceEncoding;
+use super::encoding_f32::FaceEncodingF32;
+use super::fingerprint::{FingerprintMismatch, ModelFingerprint};
+use super::index::SearchIndex;
+use super::metric::Metric;
+use super::template::{IdentityTemplate, Scoring};
+
+/// A stored person matching a face, see [`FaceComparer::find_k`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaceMatch<'a> {
+    pub key: usize,
+    pub name: &'a str,
+    pub distance: f64,
+}
 
 #[derive(Default)]
 pub struct FaceComparer {
     seed: usize,
     names: HashMap<usize, String>,
-    values: HashMap<usize, FaceEncoding>,
+    values: HashMap<usize, IdentityTemplate>,
+    metric: Metric,
+    scoring: Scoring,
+    /// Overrides the threshold of the metric.
+    tolerance: Option<f64>,
+    /// Minimum distance between the best and the second best match.
+    margin: Option<f64>,
+    /// Applied to templates created by [`FaceComparer::enroll`].
+    max_outlier_distance: Option<f64>,
+    /// The fingerprint of the first tagged encoding inserted, which all other encodings have to match.
+    fingerprint: Option<ModelFingerprint>,
+    /// Narrows down the people to compare a face against, see [`FaceComparer::with_index`].
+    index: Option<Box<dyn SearchIndex + Send + Sync>>,
 }
 
 impl FaceComparer {
-    pub fn insert(&mut self, name: String, value: FaceEncoding) {
-        let name_str = name.as_str();
-        if let Some((&key, _)) = self.names.iter().find(|(_, n)| n.as_str() == name_str) {
-            self.values.insert(key, value);
-        } else {
-            self.names.insert(self.seed, name);
-            self.values.insert(self.seed, value);
-            self.seed += 1;
+    /// The minimum number of people an index is asked for, which are then compared exactly.
+    const INDEX_CANDIDATES: usize = 16;
+
+    /// Compare faces using a different metric, along with its recommended threshold.
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        let normalized = self.normalizes_index();
+        self.metric = metric;
+        if self.index.is_some() && normalized != self.normalizes_index() {
+            self.rebuild_index();
         }
+        self
     }
 
-    pub fn find(&self, face: &FaceEncoding) -> Option<usize> {
-        const TOLERANCE: f64 = 0.6;
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
 
-        if let Some((key, x)) = self
-            .values
-            .iter()
-            .map(|(i, f)| (i, f.distance(face)))
-            .min_by(|(_, x), (_, y)| x.partial_cmp(y).unwrap())
-        {
-            if x <= TOLERANCE { Some(*key) } else { None }
-        } else {
-            None
+    /// Compare faces against the templates of each person using a different strategy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the comparer uses an index and the scoring is not [`Scoring::MinDistance`], see [`FaceComparer::with_index`].
+    pub fn with_scoring(mut self, scoring: Scoring) -> Self {
+        assert!(
+            self.index.is_none() || scoring == Scoring::MinDistance,
+            "An index can only be used with Scoring::MinDistance."
+        );
+        self.scoring = scoring;
+        self
+    }
+
+    pub fn scoring(&self) -> Scoring {
+        self.scoring
+    }
+
+    /// Accept matches up to a different distance than the threshold of the metric.
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = Some(tolerance);
+        self
+    }
+
+    /// The maximum distance of a match, which defaults to the threshold of the metric.
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance.unwrap_or_else(|| self.metric.threshold())
+    }
+
+    /// Reject ambiguous matches: a face is only identified if the best match is closer than
+    /// the second best by at least `margin`.
+    pub fn with_margin(mut self, margin: f64) -> Self {
+        self.margin = Some(margin);
+        self
+    }
+
+    pub fn margin(&self) -> Option<f64> {
+        self.margin
+    }
+
+    /// Find faces using an index, e.g. a [`HnswIndex`](super::HnswIndex) for large galleries,
+    /// instead of comparing them against every stored person.
+    ///
+    /// The index only narrows down the candidates by their closest encoding, which are then
+    /// compared using the metric of the comparer. This finds the same people as comparing
+    /// every stored person, up to the recall of the index, as long as the scoring is
+    /// [`Scoring::MinDistance`]: the mean distance or the centroid of a person may be close to
+    /// a face that none of their encodings are close to. For [`Metric::Cosine`] and
+    /// [`Metric::NormalizedEuclidean`], the index holds the encodings scaled to unit length.
+    ///
+    /// The stored people are added to the index, unless it already holds their encodings,
+    /// e.g. when it was stored along with a comparer using the same metric and loaded again.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scoring is not [`Scoring::MinDistance`].
+    pub fn with_index<I: SearchIndex + Send + Sync + 'static>(mut self, index: I) -> Self {
+        assert_eq!(
+            self.scoring,
+            Scoring::MinDistance,
+            "An index can only be used with Scoring::MinDistance."
+        );
+
+        let complete = index.len()
+            == self
+                .values
+                .values()
+                .map(IdentityTemplate::len)
+                .sum::<usize>()
+            && self
+                .values
+                .iter()
+                .all(|(&key, template)| index.count(key) == template.len());
+
+        self.index = Some(Box::new(index));
+        if !complete {
+            self.rebuild_index();
         }
+
+        self
+    }
+
+    /// Reject outliers when enrolling people, see [`IdentityTemplate::with_outlier_rejection`].
+    pub fn with_outlier_rejection(mut self, max_distance: f64) -> Self {
+        self.max_outlier_distance = Some(max_distance);
+        self
+    }
+
+    /// The fingerprint of the model that generated the stored encodings, if known.
+    pub fn fingerprint(&self) -> Option<ModelFingerprint> {
+        self.fingerprint
+    }
+
+    /// Store the encoding of a face under a name, replacing any previous encodings of that name.
+    ///
+    /// Fails if the encoding was generated by a different model than the stored encodings.
+    pub fn insert(&mut self, name: String, value: FaceEncoding) -> Result<(), FingerprintMismatch> {
+        self.insert_template(name, value.into())
+    }
+
+    /// Store the template of a person under a name, replacing any previous encodings of that name.
+    ///
+    /// Fails if the template was generated by a different model than the stored encodings.
+    pub fn insert_template(
+        &mut self,
+        name: String,
+        template: IdentityTemplate,
+    ) -> Result<(), FingerprintMismatch> {
+        self.store(name, template).map(|_| ())
+    }
+
+    /// Add the encoding of another photo to the template of a person, creating it if needed.
+    ///
+    /// Returns `false` if the encoding was rejected as an outlier, see [`FaceComparer::with_outlier_rejection`].
+    pub fn enroll(
+        &mut self,
+        name: String,
+        value: FaceEncoding,
+    ) -> Result<bool, FingerprintMismatch> {
+        FingerprintMismatch::check(self.fingerprint, value.fingerprint())?;
+
+        let key = match self.key_of(&name) {
+            Some(key) => key,
+            None => {
+                let mut template = IdentityTemplate::new();
+                if let Some(max_distance) = self.max_outlier_distance {
+                    template = template.with_outlier_rejection(max_distance);
+                }
+
+                self.store(name, template)?
+            }
+        };
+
+        let accepted = self.values.get_mut(&key).unwrap().add(value)?;
+        self.fingerprint = self.fingerprint.or(self.values[&key].fingerprint());
+        self.update_index(key);
+
+        Ok(accepted)
+    }
+
+    /// The template stored under a key.
+    pub fn template(&self, key: &usize) -> Option<&IdentityTemplate> {
+        self.values.get(key)
+    }
+
+    /// The key of the stored person matching a face, if any.
+    ///
+    /// Faces encoded by a different model than the stored encodings match nobody, see [`FaceComparer::try_find`].
+    pub fn find(&self, face: &FaceEncoding) -> Option<usize> {
+        self.try_find(face).ok().flatten()
+    }
+
+    /// Like [`FaceComparer::find`], but fails if the face was encoded by a different model than the stored encodings.
+    pub fn try_find(&self, face: &FaceEncoding) -> Result<Option<usize>, FingerprintMismatch> {
+        let ranked = self.ranked(face, 2)?;
+
+        Ok(best_match(&ranked, self.tolerance(), self.margin))
+    }
+
+    /// The `k` stored people closest to a face, starting with the closest.
+    ///
+    /// Unlike [`FaceComparer::find`], the results are not limited by the tolerance or the margin,
+    /// so their distances should be compared against [`FaceComparer::tolerance`].
+    /// Faces encoded by a different model than the stored encodings match nobody, see [`FaceComparer::try_find_k`].
+    pub fn find_k(&self, face: &FaceEncoding, k: usize) -> Vec<FaceMatch<'_>> {
+        self.try_find_k(face, k).unwrap_or_default()
+    }
+
+    /// Like [`FaceComparer::find_k`], but fails if the face was encoded by a different model than the stored encodings.
+    pub fn try_find_k(
+        &self,
+        face: &FaceEncoding,
+        k: usize,
+    ) -> Result<Vec<FaceMatch<'_>>, FingerprintMismatch> {
+        Ok(self
+            .ranked(face, k)?
+            .into_iter()
+            .map(|(key, distance)| FaceMatch {
+                key,
+                name: &self.names[&key],
+                distance,
+            })
+            .collect())
     }
 
+    /// The name stored under a key, or `None` if the key was removed or never assigned.
+    pub fn get_name(&self, key: &usize) -> Option<&str> {
+        self.names.get(key).map(String::as_str)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if no name is stored under the key, see [`FaceComparer::get_name`].
     pub fn get_name_unchecked(&self, key: &usize) -> &str {
         &self.names[key]
     }
@@ -43,10 +252,13 @@ impl FaceComparer {
     pub fn remove_key(&mut self, key: &usize) {
         self.names.remove(key);
         self.values.remove(key);
+        if let Some(index) = &mut self.index {
+            index.remove(*key);
+        }
     }
 
     pub fn remove_name(&mut self, name: &str) {
-        if let Some((&key, _)) = self.names.iter().find(|(_, n)| n.as_str() == name) {
+        if let Some(key) = self.key_of(name) {
             self.remove_key(&key);
         }
     }
@@ -58,4 +270,170 @@ impl FaceComparer {
     pub fn is_empty(&self) -> bool {
         self.names.is_empty()
     }
+
+    /// The keys and distances of the `k` closest templates, starting with the closest.
+    fn ranked(
+        &self,
+        face: &FaceEncoding,
+        k: usize,
+    ) -> Result<Vec<(usize, f64)>, FingerprintMismatch> {
+        FingerprintMismatch::check(self.fingerprint, face.fingerprint())?;
+
+        let distance = |key: usize, template: &IdentityTemplate| {
+            Some((key, template.distance(face, self.metric, self.scoring)?))
+        };
+
+        let mut ranked: Vec<_> = match &self.index {
+            Some(index) => {
+                let query = self.index_encoding(face);
+
+                index
+                    .search(&query, k.max(Self::INDEX_CANDIDATES))
+                    .into_iter()
+                    .filter_map(|(key, _)| distance(key, &self.values[&key]))
+                    .collect()
+            }
+            None => self
+                .values
+                .iter()
+                .filter_map(|(&key, template)| distance(key, template))
+                .collect(),
+        };
+        ranked.sort_unstable_by(|(_, x), (_, y)| x.total_cmp(y));
+        ranked.truncate(k);
+
+        Ok(ranked)
+    }
+
+    /// The next key to be assigned, which is never reused after removing a person.
+    pub(crate) fn seed(&self) -> usize {
+        self.seed
+    }
+
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (usize, &str, &IdentityTemplate)> {
+        self.names
+            .iter()
+            .map(|(&key, name)| (key, name.as_str(), &self.values[&key]))
+    }
+
+    /// Store a template under a specific key, as done when loading a stored comparer.
+    pub(crate) fn restore(
+        &mut self,
+        key: usize,
+        name: String,
+        template: IdentityTemplate,
+    ) -> Result<(), FingerprintMismatch> {
+        FingerprintMismatch::check(self.fingerprint, template.fingerprint())?;
+        self.fingerprint = self.fingerprint.or(template.fingerprint());
+
+        if let Some(previous) = self.key_of(&name).filter(|&previous| previous != key) {
+            self.remove_key(&previous);
+        }
+
+        self.names.insert(key, name);
+        self.values.insert(key, template);
+        self.restore_seed(key + 1);
+        self.update_index(key);
+
+        Ok(())
+    }
+
+    pub(crate) fn restore_seed(&mut self, seed: usize) {
+        self.seed = self.seed.max(seed);
+    }
+
+    /// Store a template under a name, returning its key.
+    fn store(
+        &mut self,
+        name: String,
+        template: IdentityTemplate,
+    ) -> Result<usize, FingerprintMismatch> {
+        FingerprintMismatch::check(self.fingerprint, template.fingerprint())?;
+        self.fingerprint = self.fingerprint.or(template.fingerprint());
+
+        let key = match self.key_of(&name) {
+            Some(key) => key,
+            None => {
+                let key = self.seed;
+                self.names.insert(key, name);
+                self.seed += 1;
+                key
+            }
+        };
+        self.values.insert(key, template);
+        self.update_index(key);
+
+        Ok(key)
+    }
+
+    /// Replace the encodings of a key in the index with those of its template.
+    fn update_index(&mut self, key: usize) {
+        let encodings: Vec<_> = self.values[&key]
+            .encodings()
+            .iter()
+            .map(|encoding| self.index_encoding(encoding))
+            .collect();
+
+        if let Some(index) = &mut self.index {
+            index.remove(key);
+            for encoding in &encodings {
+                index.insert(key, encoding);
+            }
+        }
+    }
+
+    /// Replace all encodings in the index with those of the stored people.
+    fn rebuild_index(&mut self) {
+        if let Some(index) = &mut self.index {
+            index.clear();
+        }
+        let keys: Vec<_> = self.values.keys().copied().collect();
+        for key in keys {
+            self.update_index(key);
+        }
+    }
+
+    /// Whether the index holds encodings scaled to unit length, because the euclidean distance
+    /// between them orders encodings like the metric of the comparer.
+    fn normalizes_index(&self) -> bool {
+        matches!(self.metric, Metric::Cosine | Metric::NormalizedEuclidean)
+    }
+
+    /// An encoding as it is stored in and searched by the index.
+    fn index_encoding(&self, encoding: &FaceEncoding) -> FaceEncodingF32 {
+        let values: &[f64] = encoding.as_ref();
+        let length = values.iter().map(|value| value * value).sum::<f64>().sqrt();
+        let scale = if self.normalizes_index() && length > 0.0 {
+            length.recip()
+        } else {
+            1.0
+        };
+
+        FaceEncodingF32::new(std::array::from_fn(|i| (values[i] * scale) as f32))
+    }
+
+    pub(crate) fn key_of(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .find(|(_, n)| n.as_str() == name)
+            .map(|(&key, _)| key)
+    }
+}
+
+/// The closest of the ranked candidates, if it lies within the tolerance
+/// and is closer than the second closest by at least the margin.
+pub(crate) fn best_match<T: Copy>(
+    ranked: &[(T, f64)],
+    tolerance: f64,
+    margin: Option<f64>,
+) -> Option<T> {
+    let best = match ranked.first() {
+        Some(&(key, distance)) if distance <= tolerance => (key, distance),
+        _ => return None,
+    };
+
+    match (margin, ranked.get(1)) {
+        (Some(margin), Some(&(_, second))) if second - best.1 < margin => None,
+        _ => Some(best.0),
+    }
 }
@@ -1,13 +1,17 @@
 use std::fmt;
 use std::slice;
 
-/// A wrapper around a `matrix<double,0,1>>`, an encoding.
+use super::fingerprint::{FingerprintMismatch, ModelFingerprint};
+use super::metric::Metric;
+use super::serialization::{self, DecodeError};
+
+/// A wrapper around a `matrix<double,0,1>>`, an encoding, tagged with the fingerprint of the model that generated it.
 #[derive(Clone)]
 pub struct FaceEncoding {
     inner: FaceEncodingInner,
 }
 
-cpp_class!(unsafe struct FaceEncodingInner as "dlib::matrix<double,0,1>");
+cpp_class!(unsafe struct FaceEncodingInner as "face_encoding");
 
 impl FaceEncoding {
     /// Create a new encoding initialised with a scalar value.
@@ -15,10 +19,10 @@ impl FaceEncoding {
     /// Mostly used for testing purposes.
     pub fn new_from_scalar(scalar: f64) -> Self {
         let inner = unsafe {
-            cpp!([scalar as "double"] -> FaceEncodingInner as "dlib::matrix<double,0,1>" {
-                auto inner = dlib::matrix<double,0,1>(128);
+            cpp!([scalar as "double"] -> FaceEncodingInner as "face_encoding" {
+                auto inner = face_encoding { dlib::matrix<double,0,1>(128) };
                 for (size_t i = 0; i < 128; i++) {
-                    inner(i) = scalar;
+                    inner.values(i) = scalar;
                 }
 
                 return inner;
@@ -30,16 +34,18 @@ impl FaceEncoding {
 
     /// Create a new encoding using previously stored values
     /// from a f64 Vec.
+    ///
+    /// The encoding has no fingerprint, see [`FaceEncoding::with_fingerprint`].
     pub fn from_vec(values: &Vec<f64>) -> Result<Self, ArraySizeError> {
         match values.len() {
             128 => {
                 let values = values.as_ptr();
                 let inner = unsafe {
-                    cpp!([values as "const double *"] -> FaceEncodingInner as "dlib::matrix<double,0,1>" {
+                    cpp!([values as "const double *"] -> FaceEncodingInner as "face_encoding" {
 
-                        auto inner = dlib::matrix<double,0,1>(128);
+                        auto inner = face_encoding { dlib::matrix<double,0,1>(128) };
                         for (int i = 0; i < 128; i++) {
-                            inner(i) = values[i];
+                            inner.values(i) = values[i];
                         }
 
                         return inner;
@@ -52,17 +58,105 @@ impl FaceEncoding {
         }
     }
 
+    /// The fingerprint of the model that generated the encoding, if known.
+    pub fn fingerprint(&self) -> Option<ModelFingerprint> {
+        let inner = &self.inner;
+
+        let fingerprint = unsafe {
+            cpp!([inner as "const face_encoding*"] -> u64 as "uint64_t" {
+                return inner->fingerprint;
+            })
+        };
+
+        ModelFingerprint::from_u64(fingerprint)
+    }
+
+    /// Tag the encoding with the fingerprint of the model that generated it,
+    /// e.g. after loading its values from storage.
+    pub fn with_fingerprint(mut self, fingerprint: Option<ModelFingerprint>) -> Self {
+        let inner = &mut self.inner;
+        let fingerprint = fingerprint.map_or(0, |fingerprint| fingerprint.as_u64());
+
+        unsafe {
+            cpp!([inner as "face_encoding*", fingerprint as "uint64_t"] {
+                inner->fingerprint = fingerprint;
+            })
+        }
+
+        self
+    }
+
     /// Calculate the euclidean distance between two encodings.
     ///
     /// This value can be compared to a constant to determine if the faces are the same or not.
     /// A good value for this is `0.6`.
+    ///
+    /// This is unchecked: encodings of different models are compared as if they were compatible,
+    /// which gives a meaningless distance. Use [`FaceEncoding::try_distance`] unless both
+    /// encodings are known to come from the same model.
     pub fn distance(&self, other: &Self) -> f64 {
+        let (this, other) = (&self.inner, &other.inner);
+
         unsafe {
-            cpp!([self as "const dlib::matrix<double,0,1>*", other as "const dlib::matrix<double,0,1>*"] -> f64 as "double" {
-                return dlib::length(*self - *other);
+            cpp!([this as "const face_encoding*", other as "const face_encoding*"] -> f64 as "double" {
+                return dlib::length(this->values - other->values);
             })
         }
     }
+
+    /// Calculate the euclidean distance between two encodings,
+    /// failing if they were generated by different models.
+    ///
+    /// Encodings without a fingerprint can be compared with any encoding.
+    pub fn try_distance(&self, other: &Self) -> Result<f64, FingerprintMismatch> {
+        FingerprintMismatch::check(self.fingerprint(), other.fingerprint())?;
+
+        Ok(self.distance(other))
+    }
+
+    /// Store the encoding and its fingerprint in the compact binary format, see [`serialization`](super::serialization).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serialization::encode(self.as_ref(), self.fingerprint())
+    }
+
+    /// Load an encoding stored with [`FaceEncoding::to_bytes`] or [`FaceEncodingF32::to_bytes`](super::FaceEncodingF32::to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (values, fingerprint) = serialization::decode(bytes)?;
+
+        Self::from_vec(&values)
+            .map(|encoding| encoding.with_fingerprint(fingerprint))
+            .map_err(|_| DecodeError::InvalidLength(values.len()))
+    }
+
+    /// Store the encoding in the compact binary format, encoded as base64 text.
+    pub fn to_base64(&self) -> String {
+        serialization::encode_base64(&self.to_bytes())
+    }
+
+    /// Load an encoding stored with [`FaceEncoding::to_base64`].
+    pub fn from_base64(text: &str) -> Result<Self, DecodeError> {
+        Self::from_bytes(&serialization::decode_base64(text)?)
+    }
+
+    /// Calculate the distance between two encodings using a specific metric.
+    ///
+    /// The result should be compared against the threshold of that metric, see [`Metric::threshold`].
+    /// Like [`FaceEncoding::distance`] this is unchecked, see [`FaceEncoding::try_distance_with`].
+    pub fn distance_with(&self, other: &Self, metric: Metric) -> f64 {
+        metric.distance(self.as_ref(), other.as_ref())
+    }
+
+    /// Calculate the distance between two encodings using a specific metric,
+    /// failing if they were generated by different models.
+    pub fn try_distance_with(
+        &self,
+        other: &Self,
+        metric: Metric,
+    ) -> Result<f64, FingerprintMismatch> {
+        FingerprintMismatch::check(self.fingerprint(), other.fingerprint())?;
+
+        Ok(self.distance_with(other, metric))
+    }
 }
 
 impl fmt::Debug for FaceEncoding {
@@ -73,17 +167,17 @@ impl fmt::Debug for FaceEncoding {
 
 impl PartialEq for FaceEncoding {
     fn eq(&self, other: &Self) -> bool {
-        self.as_ref().eq(other.as_ref())
+        self.fingerprint() == other.fingerprint() && self.as_ref().eq(other.as_ref())
     }
 }
 
 impl AsRef<[f64]> for FaceEncoding {
     fn as_ref(&self) -> &[f64] {
-        let matrix = &self.inner;
+        let inner = &self.inner;
 
         let len = unsafe {
-            cpp!([matrix as "const dlib::matrix<double,0,1>*"] -> usize as "size_t" {
-                return matrix->size();
+            cpp!([inner as "const face_encoding*"] -> usize as "size_t" {
+                return inner->values.size();
             })
         };
 
@@ -91,8 +185,8 @@ impl AsRef<[f64]> for FaceEncoding {
             &[]
         } else {
             unsafe {
-                let pointer = cpp!([matrix as "dlib::matrix<double,0,1>*"] -> *const f64 as "double*" {
-                    return &(*matrix)(0);
+                let pointer = cpp!([inner as "face_encoding*"] -> *const f64 as "double*" {
+                    return &(inner->values)(0);
                 });
 
                 slice::from_raw_parts(pointer, len)
@@ -109,6 +203,46 @@ impl TryFrom<Vec<f64>> for FaceEncoding {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for FaceEncoding {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("FaceEncoding", 2)?;
+        state.serialize_field("values", self.as_ref())?;
+        state.serialize_field("fingerprint", &self.fingerprint())?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for FaceEncoding {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // encodings without a fingerprint may also be stored as a plain sequence of values
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Values(Vec<f64>),
+            Tagged {
+                values: Vec<f64>,
+                fingerprint: Option<ModelFingerprint>,
+            },
+        }
+
+        let (values, fingerprint) = match serde::Deserialize::deserialize(deserializer)? {
+            Repr::Values(values) => (values, None),
+            Repr::Tagged {
+                values,
+                fingerprint,
+            } => (values, fingerprint),
+        };
+
+        Self::from_vec(&values)
+            .map(|encoding| encoding.with_fingerprint(fingerprint))
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 pub struct ArraySizeError;
 
 impl fmt::Debug for ArraySizeError {
@@ -132,4 +266,34 @@ fn encoding_test() {
     assert_ne!(encoding_a, encoding_b);
 
     assert_eq!(encoding_a.distance(&encoding_b), 128.0_f64.sqrt());
+    assert_eq!(
+        encoding_a.distance_with(&encoding_b, Metric::Euclidean),
+        encoding_a.distance(&encoding_b)
+    );
+    assert_eq!(
+        encoding_a.distance_with(&encoding_b, Metric::SquaredEuclidean),
+        128.0
+    );
+
+    // only the fallible comparisons check the fingerprints
+    let tagged = encoding_b
+        .clone()
+        .with_fingerprint(ModelFingerprint::from_u64(1));
+    let other = encoding_a
+        .clone()
+        .with_fingerprint(ModelFingerprint::from_u64(2));
+    assert_eq!(tagged.distance(&other), 128.0_f64.sqrt());
+    assert!(tagged.try_distance(&other).is_err());
+    assert!(tagged.try_distance_with(&other, Metric::Cosine).is_err());
+    assert_eq!(tagged.try_distance(&encoding_a), Ok(128.0_f64.sqrt()));
+
+    let encoding = FaceEncoding::from_vec(&(0..128).map(|i| i as f64 / 7.0).collect()).unwrap();
+    assert_eq!(
+        FaceEncoding::from_bytes(&encoding.to_bytes()),
+        Ok(encoding.clone())
+    );
+    assert_eq!(
+        FaceEncoding::from_base64(&encoding.to_base64()),
+        Ok(encoding)
+    );
 }
@@ -0,0 +1,172 @@
+use std::ops::Deref;
+
+use super::encoding::{ArraySizeError, FaceEncoding};
+use super::metric::Metric;
+use super::serialization::{self, DecodeError};
+
+/// A face encoding stored as 128 single precision floats, the precision the network outputs.
+///
+/// Unlike [`FaceEncoding`] this is a plain Rust value without a C++ object behind it,
+/// which halves the memory used by large collections of encodings. It carries no
+/// fingerprint, so collections of them should keep the [`ModelFingerprint`](super::ModelFingerprint) of the
+/// model that generated them once, see [`FaceEncoderNetwork::fingerprint`](super::FaceEncoderNetwork::fingerprint).
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(transparent)]
+pub struct FaceEncodingF32([f32; FaceEncodingF32::LEN]);
+
+impl FaceEncodingF32 {
+    /// The number of values in an encoding.
+    pub const LEN: usize = 128;
+
+    pub fn new(values: [f32; Self::LEN]) -> Self {
+        Self(values)
+    }
+
+    /// Create a new encoding from previously stored values.
+    pub fn from_slice(values: &[f32]) -> Result<Self, ArraySizeError> {
+        values.try_into().map(Self).map_err(|_| ArraySizeError)
+    }
+
+    /// Calculate the euclidean distance between two encodings.
+    ///
+    /// This matches [`FaceEncoding::distance`], so the same threshold of `0.6` applies.
+    pub fn distance(&self, other: &Self) -> f32 {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    /// Calculate the distance between two encodings using a specific metric.
+    pub fn distance_with(&self, other: &Self, metric: Metric) -> f32 {
+        metric.distance_f32(&self.0, &other.0)
+    }
+
+    /// Store the encoding in the compact binary format without a fingerprint, see [`serialization`](super::serialization).
+    pub fn to_bytes(self) -> Vec<u8> {
+        serialization::encode(&self.0, None)
+    }
+
+    /// Load an encoding stored with [`FaceEncodingF32::to_bytes`] or [`FaceEncoding::to_bytes`].
+    ///
+    /// Values stored in double precision are rounded to the nearest `f32`. A stored fingerprint
+    /// is dropped, use [`FaceEncoding::from_bytes`] to keep it.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (values, _) = serialization::decode(bytes)?;
+        let values: Vec<f32> = values.into_iter().map(|value| value as f32).collect();
+
+        Self::from_slice(&values).map_err(|_| DecodeError::InvalidLength(values.len()))
+    }
+
+    /// Store the encoding in the compact binary format, encoded as base64 text.
+    pub fn to_base64(self) -> String {
+        serialization::encode_base64(&self.to_bytes())
+    }
+
+    /// Load an encoding stored with [`FaceEncodingF32::to_base64`].
+    pub fn from_base64(text: &str) -> Result<Self, DecodeError> {
+        Self::from_bytes(&serialization::decode_base64(text)?)
+    }
+
+    /// Convert the encoding into a double precision [`FaceEncoding`] without a fingerprint,
+    /// see [`FaceEncoding::with_fingerprint`].
+    pub fn to_encoding(self) -> FaceEncoding {
+        let values: Vec<f64> = self.0.iter().map(|&value| value as f64).collect();
+
+        FaceEncoding::from_vec(&values).unwrap()
+    }
+}
+
+impl Deref for FaceEncodingF32 {
+    type Target = [f32; FaceEncodingF32::LEN];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<[f32]> for FaceEncodingF32 {
+    fn as_ref(&self) -> &[f32] {
+        &self.0
+    }
+}
+
+impl From<[f32; FaceEncodingF32::LEN]> for FaceEncodingF32 {
+    fn from(values: [f32; FaceEncodingF32::LEN]) -> Self {
+        Self(values)
+    }
+}
+
+impl From<&FaceEncodingF32> for FaceEncoding {
+    fn from(encoding: &FaceEncodingF32) -> Self {
+        encoding.to_encoding()
+    }
+}
+
+impl TryFrom<&FaceEncoding> for FaceEncodingF32 {
+    type Error = ArraySizeError;
+
+    /// Convert a double precision encoding, rounding every value to the nearest `f32`
+    /// and dropping its fingerprint.
+    fn try_from(encoding: &FaceEncoding) -> Result<Self, Self::Error> {
+        let values: Vec<f32> = encoding
+            .as_ref()
+            .iter()
+            .map(|&value| value as f32)
+            .collect();
+
+        Self::from_slice(&values)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for FaceEncodingF32 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(self.0.as_slice(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for FaceEncodingF32 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values: Vec<f32> = serde::Deserialize::deserialize(deserializer)?;
+
+        Self::from_slice(&values).map_err(serde::de::Error::custom)
+    }
+}
+
+#[test]
+fn encoding_f32_test() {
+    use super::fingerprint::ModelFingerprint;
+
+    let encoding = FaceEncoding::new_from_scalar(0.5);
+    let encoding_f32 = FaceEncodingF32::try_from(&encoding).unwrap();
+
+    assert_eq!(encoding_f32, FaceEncodingF32::new([0.5; 128]));
+    assert_eq!(encoding_f32.to_encoding(), encoding);
+    assert_eq!(
+        encoding_f32.distance(&FaceEncodingF32::new([1.5; 128])),
+        128.0_f32.sqrt()
+    );
+
+    assert!(FaceEncodingF32::from_slice(&[0.0; 127]).is_err());
+
+    assert_eq!(
+        FaceEncodingF32::from_base64(&encoding_f32.to_base64()),
+        Ok(encoding_f32)
+    );
+    assert_eq!(
+        FaceEncoding::from_bytes(&encoding_f32.to_bytes()),
+        Ok(encoding)
+    );
+
+    // the fingerprint is kept by the collection, not the encoding
+    assert_eq!(size_of::<FaceEncodingF32>(), size_of::<[f32; 128]>());
+    let tagged = FaceEncoding::new_from_scalar(0.5).with_fingerprint(ModelFingerprint::from_u64(1));
+    assert_eq!(
+        FaceEncodingF32::from_bytes(&tagged.to_bytes()),
+        Ok(encoding_f32)
+    );
+}
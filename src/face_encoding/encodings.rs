@@ -4,8 +4,8 @@ use std::slice;
 use super::encoding::FaceEncoding;
 
 cpp_class!(
-    /// A wrapper around a `std::vector<matrix<double,0,1>>`, a vector of encodings.
-    pub unsafe struct FaceEncodings as "std::vector<dlib::matrix<double,0,1>>"
+    /// A wrapper around a `std::vector` of encodings.
+    pub unsafe struct FaceEncodings as "std::vector<face_encoding>"
 );
 
 impl Deref for FaceEncodings {
@@ -13,7 +13,7 @@ impl Deref for FaceEncodings {
 
     fn deref(&self) -> &Self::Target {
         let len = unsafe {
-            cpp!([self as "std::vector<dlib::matrix<double,0,1>>*"] -> usize as "size_t" {
+            cpp!([self as "std::vector<face_encoding>*"] -> usize as "size_t" {
                 return self->size();
             })
         };
@@ -22,7 +22,7 @@ impl Deref for FaceEncodings {
             &[]
         } else {
             unsafe {
-                let pointer = cpp!([self as "std::vector<dlib::matrix<double,0,1>>*"] -> *const FaceEncoding as "dlib::matrix<double,0,1>*" {
+                let pointer = cpp!([self as "std::vector<face_encoding>*"] -> *const FaceEncoding as "face_encoding*" {
                     return &(*self)[0];
                 });
 
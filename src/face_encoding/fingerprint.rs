@@ -0,0 +1,166 @@
+use std::fmt;
+use std::num::NonZeroU64;
+use std::str::FromStr;
+
+use super::options::EncodingOptions;
+
+/// Identifies the network and settings an encoding was generated with.
+///
+/// Encodings are only comparable when they were generated by the same network with the same chip
+/// settings. Encodings without a fingerprint, e.g. created with [`FaceEncoding::from_vec`](super::FaceEncoding::from_vec),
+/// are assumed to be comparable with any other encoding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ModelFingerprint(NonZeroU64);
+
+impl ModelFingerprint {
+    /// Combine the hash of a serialized network with the options that change how faces are cropped.
+    ///
+    /// Jittering only reduces noise around the same encoding, so its settings are not part of the fingerprint.
+    pub(crate) fn new(network_hash: u64, options: &EncodingOptions) -> Self {
+        let mut hash = Fnv1a::default();
+        hash.write(&network_hash.to_le_bytes());
+        hash.write(&options.padding.to_bits().to_le_bytes());
+        hash.write(&[options.flip as u8]);
+
+        Self::from_u64(hash.finish()).unwrap_or(Self(NonZeroU64::MIN))
+    }
+
+    /// Restore a fingerprint from its numeric value, where `0` means no fingerprint.
+    pub fn from_u64(value: u64) -> Option<Self> {
+        NonZeroU64::new(value).map(Self)
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+impl fmt::Display for ModelFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for ModelFingerprint {
+    type Err = ParseFingerprintError;
+
+    /// Parse a fingerprint from its hexadecimal [`Display`](fmt::Display) form.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16)
+            .ok()
+            .and_then(Self::from_u64)
+            .ok_or(ParseFingerprintError)
+    }
+}
+
+/// A string is not a non-zero hexadecimal model fingerprint.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseFingerprintError;
+
+impl fmt::Display for ParseFingerprintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid model fingerprint.")
+    }
+}
+
+impl std::error::Error for ParseFingerprintError {}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ModelFingerprint {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ModelFingerprint {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text: String = serde::Deserialize::deserialize(deserializer)?;
+
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Two encodings with different fingerprints were compared.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FingerprintMismatch {
+    pub expected: ModelFingerprint,
+    pub found: ModelFingerprint,
+}
+
+impl FingerprintMismatch {
+    /// Check whether encodings with the given fingerprints may be compared.
+    pub(crate) fn check(
+        expected: Option<ModelFingerprint>,
+        found: Option<ModelFingerprint>,
+    ) -> Result<(), Self> {
+        match (expected, found) {
+            (Some(expected), Some(found)) if expected != found => Err(Self { expected, found }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for FingerprintMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Face encodings are not comparable: expected model fingerprint {}, found {}.",
+            self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for FingerprintMismatch {}
+
+/// The 64 bit FNV-1a hash, which is stable across platforms and releases, unlike [`std::hash`].
+#[derive(Copy, Clone)]
+pub(crate) struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[test]
+fn fingerprint_test() {
+    let options = EncodingOptions::default();
+    let fingerprint = ModelFingerprint::new(42, &options);
+
+    assert_eq!(fingerprint, ModelFingerprint::new(42, &options));
+    assert_ne!(fingerprint, ModelFingerprint::new(43, &options));
+    assert_ne!(
+        fingerprint,
+        ModelFingerprint::new(
+            42,
+            &EncodingOptions {
+                flip: true,
+                ..options
+            }
+        )
+    );
+    assert_eq!(
+        fingerprint,
+        ModelFingerprint::new(42, &EncodingOptions { seed: 7, ..options })
+    );
+
+    assert_eq!(fingerprint.to_string().parse(), Ok(fingerprint));
+
+    let other = ModelFingerprint::from_u64(1).unwrap();
+    assert!(FingerprintMismatch::check(Some(fingerprint), None).is_ok());
+    assert!(FingerprintMismatch::check(Some(fingerprint), Some(other)).is_err());
+}
@@ -0,0 +1,241 @@
+use std::collections::{HashMap, HashSet};
+
+use super::encoding_f32::FaceEncodingF32;
+use super::index::SearchIndex;
+use super::simd;
+
+/// How a [`FlatIndex`] stores its encodings.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Quantization {
+    /// Single precision floats, which compare exactly like [`BruteForceIndex`](super::BruteForceIndex).
+    #[default]
+    None,
+    /// Half precision floats, which halve the memory at a negligible loss of precision.
+    Fp16,
+    /// Bytes scaled to the largest value of every encoding, which quarter the memory
+    /// at a small loss of precision, see [`measure_recall`](super::measure_recall).
+    Int8,
+}
+
+#[derive(Clone, Debug)]
+enum Storage {
+    F32(Vec<f32>),
+    Fp16(Vec<u16>),
+    Int8 { values: Vec<i8>, scales: Vec<f32> },
+}
+
+/// An exact index comparing the query against every stored encoding, like [`BruteForceIndex`](super::BruteForceIndex),
+/// but using the vector instructions of the CPU on encodings stored next to each other.
+///
+/// Searching 100k encodings takes a few milliseconds, so an approximate index
+/// like [`HnswIndex`](super::HnswIndex) only pays off for larger galleries.
+/// Removing encodings moves all encodings stored after them.
+#[derive(Clone, Debug)]
+pub struct FlatIndex {
+    quantization: Quantization,
+    storage: Storage,
+    /// The key of every stored encoding, in storage order.
+    keys: Vec<usize>,
+    counts: HashMap<usize, usize>,
+}
+
+impl Default for FlatIndex {
+    fn default() -> Self {
+        Self::new(Quantization::default())
+    }
+}
+
+impl FlatIndex {
+    pub fn new(quantization: Quantization) -> Self {
+        let storage = match quantization {
+            Quantization::None => Storage::F32(Vec::new()),
+            Quantization::Fp16 => Storage::Fp16(Vec::new()),
+            Quantization::Int8 => Storage::Int8 {
+                values: Vec::new(),
+                scales: Vec::new(),
+            },
+        };
+
+        Self {
+            quantization,
+            storage,
+            keys: Vec::new(),
+            counts: HashMap::new(),
+        }
+    }
+
+    pub fn quantization(&self) -> Quantization {
+        self.quantization
+    }
+
+    /// The squared distance between the query and every stored encoding, in storage order.
+    fn squared_distances(&self, query: &[f32]) -> Vec<f32> {
+        let len = FaceEncodingF32::LEN;
+
+        match &self.storage {
+            Storage::F32(values) => values
+                .chunks_exact(len)
+                .map(|values| simd::squared_distance(query, values))
+                .collect(),
+            Storage::Fp16(values) => values
+                .chunks_exact(len)
+                .map(|values| simd::squared_distance_f16(query, values))
+                .collect(),
+            Storage::Int8 { values, scales } => values
+                .chunks_exact(len)
+                .zip(scales)
+                .map(|(values, &scale)| simd::squared_distance_i8(query, values, scale))
+                .collect(),
+        }
+    }
+}
+
+impl SearchIndex for FlatIndex {
+    fn insert(&mut self, key: usize, encoding: &FaceEncodingF32) {
+        match &mut self.storage {
+            Storage::F32(values) => values.extend_from_slice(encoding.as_ref()),
+            Storage::Fp16(values) => {
+                values.extend(encoding.iter().map(|&value| simd::f32_to_f16(value)))
+            }
+            Storage::Int8 { values, scales } => {
+                let max = encoding
+                    .iter()
+                    .fold(0.0f32, |max, value| max.max(value.abs()));
+                let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+
+                values.extend(encoding.iter().map(|&value| (value / scale).round() as i8));
+                scales.push(scale);
+            }
+        }
+
+        self.keys.push(key);
+        *self.counts.entry(key).or_default() += 1;
+    }
+
+    fn remove(&mut self, key: usize) {
+        if self.counts.remove(&key).is_none() {
+            return;
+        }
+
+        let keep: Vec<_> = self.keys.iter().map(|&stored| stored != key).collect();
+        match &mut self.storage {
+            Storage::F32(values) => retain_rows(values, FaceEncodingF32::LEN, &keep),
+            Storage::Fp16(values) => retain_rows(values, FaceEncodingF32::LEN, &keep),
+            Storage::Int8 { values, scales } => {
+                retain_rows(values, FaceEncodingF32::LEN, &keep);
+                retain_rows(scales, 1, &keep);
+            }
+        }
+        retain_rows(&mut self.keys, 1, &keep);
+    }
+
+    fn search(&self, query: &FaceEncodingF32, k: usize) -> Vec<(usize, f32)> {
+        if k == 0 || self.keys.is_empty() {
+            return Vec::new();
+        }
+
+        let distances = self.squared_distances(query.as_ref());
+        let by_distance = |&a: &usize, &b: &usize| distances[a].total_cmp(&distances[b]);
+
+        // at most this many of the closest encodings share their key with a closer one,
+        // so the closest `k + duplicates` encodings hold the closest `k` keys
+        let duplicates = self.keys.len() - self.counts.len();
+        let candidates = (k + duplicates).min(self.keys.len());
+
+        let mut rows: Vec<_> = (0..self.keys.len()).collect();
+        if candidates < rows.len() {
+            rows.select_nth_unstable_by(candidates - 1, by_distance);
+            rows.truncate(candidates);
+        }
+        rows.sort_unstable_by(by_distance);
+
+        let mut found = HashSet::new();
+        rows.into_iter()
+            .filter(|&row| found.insert(self.keys[row]))
+            .take(k)
+            .map(|row| (self.keys[row], distances[row].sqrt()))
+            .collect()
+    }
+
+    fn count(&self, key: usize) -> usize {
+        self.counts.get(&key).copied().unwrap_or(0)
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn clear(&mut self) {
+        *self = Self::new(self.quantization);
+    }
+}
+
+/// Keep the rows of `width` values marked in `keep`, preserving their order.
+fn retain_rows<T: Copy>(values: &mut Vec<T>, width: usize, keep: &[bool]) {
+    let mut kept = 0;
+    for (row, &keep) in keep.iter().enumerate() {
+        if keep {
+            values.copy_within(row * width..(row + 1) * width, kept * width);
+            kept += 1;
+        }
+    }
+    values.truncate(kept * width);
+}
+
+#[test]
+fn flat_index_test() {
+    use super::index::{BruteForceIndex, measure_recall};
+
+    // deterministic pseudo random encodings
+    let mut state = 7u64;
+    let mut random = move || {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (state >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+    };
+    let encodings: Vec<FaceEncodingF32> = (0..300)
+        .map(|_| FaceEncodingF32::new(std::array::from_fn(|_| random() * 0.2)))
+        .collect();
+
+    let mut exact = BruteForceIndex::new();
+    let mut indices: Vec<_> = [Quantization::None, Quantization::Fp16, Quantization::Int8]
+        .into_iter()
+        .map(FlatIndex::new)
+        .collect();
+    for (key, encoding) in encodings.iter().enumerate() {
+        // every key holds two encodings
+        exact.insert(key / 2, encoding);
+        for index in &mut indices {
+            index.insert(key / 2, encoding);
+        }
+    }
+
+    let queries = &encodings[..30];
+    for index in &indices {
+        assert_eq!(index.len(), 300);
+        assert_eq!(index.count(7), 2);
+
+        let recall = measure_recall(index, &exact, queries, 10);
+        match index.quantization() {
+            Quantization::None => assert_eq!(recall, 1.0),
+            _ => assert!(recall > 0.9, "recall {recall}"),
+        }
+    }
+
+    let flat = &mut indices[0];
+    let expected = exact.search(&encodings[3], 5);
+    let found = flat.search(&encodings[3], 5);
+    assert_eq!(found.len(), 5);
+    for ((key, distance), (expected_key, expected_distance)) in found.into_iter().zip(expected) {
+        assert_eq!(key, expected_key);
+        assert!((distance - expected_distance).abs() < 1e-5);
+    }
+
+    flat.remove(1);
+    assert_eq!(flat.len(), 298);
+    assert_eq!(flat.count(1), 0);
+    assert_ne!(flat.search(&encodings[3], 1)[0].0, 1);
+    assert_eq!(flat.search(&encodings[100], 1)[0], (50, 0.0));
+}
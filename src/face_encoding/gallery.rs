@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::compare::best_match;
+use super::encoding::FaceEncoding;
+use super::fingerprint::{FingerprintMismatch, ModelFingerprint};
+use super::metric::Metric;
+use super::template::{IdentityTemplate, Scoring};
+
+/// A stored identity matching a face, see [`Gallery::find_k`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct GalleryMatch<'a, K, M> {
+    pub key: &'a K,
+    pub metadata: &'a M,
+    pub distance: f64,
+}
+
+/// Identities stored under keys of your choice, e.g. database ids, each with arbitrary metadata.
+///
+/// Unlike [`FaceComparer`](super::FaceComparer), which assigns its own keys and only stores
+/// a name, a gallery is keyed by the caller and never panics when looking up a key.
+#[derive(Clone, Debug)]
+pub struct Gallery<K, M = ()> {
+    identities: HashMap<K, (IdentityTemplate, M)>,
+    metric: Metric,
+    scoring: Scoring,
+    /// Overrides the threshold of the metric.
+    tolerance: Option<f64>,
+    /// Minimum distance between the best and the second best match.
+    margin: Option<f64>,
+    /// The fingerprint of the first tagged encoding inserted, which all other encodings have to match.
+    fingerprint: Option<ModelFingerprint>,
+}
+
+impl<K, M> Default for Gallery<K, M> {
+    fn default() -> Self {
+        Self {
+            identities: HashMap::new(),
+            metric: Metric::default(),
+            scoring: Scoring::default(),
+            tolerance: None,
+            margin: None,
+            fingerprint: None,
+        }
+    }
+}
+
+impl<K: Eq + Hash, M> Gallery<K, M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compare faces using a different metric, along with its recommended threshold.
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    /// Compare faces against the templates of each identity using a different strategy.
+    pub fn with_scoring(mut self, scoring: Scoring) -> Self {
+        self.scoring = scoring;
+        self
+    }
+
+    pub fn scoring(&self) -> Scoring {
+        self.scoring
+    }
+
+    /// Accept matches up to a different distance than the threshold of the metric.
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = Some(tolerance);
+        self
+    }
+
+    /// The maximum distance of a match, which defaults to the threshold of the metric.
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance.unwrap_or_else(|| self.metric.threshold())
+    }
+
+    /// Reject ambiguous matches: a face is only identified if the best match is closer than
+    /// the second best by at least `margin`.
+    pub fn with_margin(mut self, margin: f64) -> Self {
+        self.margin = Some(margin);
+        self
+    }
+
+    pub fn margin(&self) -> Option<f64> {
+        self.margin
+    }
+
+    /// The fingerprint of the model that generated the stored encodings, if known.
+    pub fn fingerprint(&self) -> Option<ModelFingerprint> {
+        self.fingerprint
+    }
+
+    /// Store the encoding of a face and its metadata under a key, replacing any previous identity
+    /// stored under that key.
+    ///
+    /// Returns the metadata of the replaced identity.
+    /// Fails if the encoding was generated by a different model than the stored encodings.
+    pub fn insert(
+        &mut self,
+        key: K,
+        encoding: FaceEncoding,
+        metadata: M,
+    ) -> Result<Option<M>, FingerprintMismatch> {
+        self.insert_template(key, encoding.into(), metadata)
+    }
+
+    /// Store the template of an identity and its metadata under a key, replacing any previous
+    /// identity stored under that key.
+    ///
+    /// Returns the metadata of the replaced identity.
+    /// Fails if the template was generated by a different model than the stored encodings.
+    pub fn insert_template(
+        &mut self,
+        key: K,
+        template: IdentityTemplate,
+        metadata: M,
+    ) -> Result<Option<M>, FingerprintMismatch> {
+        FingerprintMismatch::check(self.fingerprint, template.fingerprint())?;
+        self.fingerprint = self.fingerprint.or(template.fingerprint());
+
+        Ok(self
+            .identities
+            .insert(key, (template, metadata))
+            .map(|(_, metadata)| metadata))
+    }
+
+    /// Add the encoding of another photo to the template of an identity.
+    ///
+    /// Returns `None` if no identity is stored under the key, or `Some(false)` if the encoding
+    /// was rejected as an outlier, see [`IdentityTemplate::with_outlier_rejection`].
+    pub fn enroll(
+        &mut self,
+        key: &K,
+        encoding: FaceEncoding,
+    ) -> Result<Option<bool>, FingerprintMismatch> {
+        FingerprintMismatch::check(self.fingerprint, encoding.fingerprint())?;
+
+        let Some((template, _)) = self.identities.get_mut(key) else {
+            return Ok(None);
+        };
+        let accepted = template.add(encoding)?;
+        self.fingerprint = self.fingerprint.or(template.fingerprint());
+
+        Ok(Some(accepted))
+    }
+
+    /// Remove the identity stored under a key, returning its metadata.
+    pub fn remove(&mut self, key: &K) -> Option<M> {
+        self.identities.remove(key).map(|(_, metadata)| metadata)
+    }
+
+    /// The metadata stored under a key.
+    pub fn get(&self, key: &K) -> Option<&M> {
+        self.identities.get(key).map(|(_, metadata)| metadata)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut M> {
+        self.identities.get_mut(key).map(|(_, metadata)| metadata)
+    }
+
+    /// The template stored under a key.
+    pub fn template(&self, key: &K) -> Option<&IdentityTemplate> {
+        self.identities.get(key).map(|(template, _)| template)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.identities.contains_key(key)
+    }
+
+    /// The keys and metadata of all stored identities, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &M)> {
+        self.identities
+            .iter()
+            .map(|(key, (_, metadata))| (key, metadata))
+    }
+
+    /// The key and metadata of the stored identity matching a face, if any.
+    ///
+    /// Faces encoded by a different model than the stored encodings match nobody, see [`Gallery::try_find`].
+    pub fn find(&self, face: &FaceEncoding) -> Option<(&K, &M)> {
+        self.try_find(face).ok().flatten()
+    }
+
+    /// Like [`Gallery::find`], but fails if the face was encoded by a different model than the stored encodings.
+    pub fn try_find(&self, face: &FaceEncoding) -> Result<Option<(&K, &M)>, FingerprintMismatch> {
+        let ranked = self.ranked(face, 2)?;
+
+        Ok(best_match(&ranked, self.tolerance(), self.margin)
+            .and_then(|key| self.identities.get_key_value(key))
+            .map(|(key, (_, metadata))| (key, metadata)))
+    }
+
+    /// The `k` stored identities closest to a face, starting with the closest.
+    ///
+    /// Unlike [`Gallery::find`], the results are not limited by the tolerance or the margin,
+    /// so their distances should be compared against [`Gallery::tolerance`].
+    /// Faces encoded by a different model than the stored encodings match nobody, see [`Gallery::try_find_k`].
+    pub fn find_k(&self, face: &FaceEncoding, k: usize) -> Vec<GalleryMatch<'_, K, M>> {
+        self.try_find_k(face, k).unwrap_or_default()
+    }
+
+    /// Like [`Gallery::find_k`], but fails if the face was encoded by a different model than the stored encodings.
+    pub fn try_find_k(
+        &self,
+        face: &FaceEncoding,
+        k: usize,
+    ) -> Result<Vec<GalleryMatch<'_, K, M>>, FingerprintMismatch> {
+        Ok(self
+            .ranked(face, k)?
+            .into_iter()
+            .map(|(key, distance)| GalleryMatch {
+                key,
+                metadata: &self.identities[key].1,
+                distance,
+            })
+            .collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.identities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.identities.is_empty()
+    }
+
+    /// The keys and distances of the `k` closest templates, starting with the closest.
+    fn ranked(&self, face: &FaceEncoding, k: usize) -> Result<Vec<(&K, f64)>, FingerprintMismatch> {
+        FingerprintMismatch::check(self.fingerprint, face.fingerprint())?;
+
+        let mut ranked: Vec<_> = self
+            .identities
+            .iter()
+            .filter_map(|(key, (template, _))| {
+                Some((key, template.distance(face, self.metric, self.scoring)?))
+            })
+            .collect();
+        ranked.sort_unstable_by(|(_, x), (_, y)| x.total_cmp(y));
+        ranked.truncate(k);
+
+        Ok(ranked)
+    }
+}
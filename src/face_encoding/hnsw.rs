@@ -0,0 +1,613 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use super::encoding_f32::FaceEncodingF32;
+use super::index::{SearchIndex, squared_distance};
+use super::persistence::{self, PersistenceError, Reader};
+
+const MAGIC: &[u8; 4] = b"DFRH";
+const VERSION: u8 = 1;
+
+/// The highest layer a node can be placed on, far above what any realistic gallery reaches.
+const MAX_LEVEL: usize = 16;
+
+/// Tuning parameters of a [`HnswIndex`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HnswParams {
+    /// The number of neighbours of a node, twice as many on the lowest layer.
+    ///
+    /// More neighbours increase the recall at the cost of memory and insertion time.
+    pub m: usize,
+    /// The number of candidates considered when inserting an encoding.
+    ///
+    /// Higher values build a better graph, increasing the recall, but slow down insertions.
+    pub ef_construction: usize,
+    /// The number of candidates considered when searching.
+    ///
+    /// Higher values increase the recall but slow down searches.
+    pub ef_search: usize,
+    /// Seeds the random layers of new nodes, so the same insertions always build the same graph.
+    pub seed: u64,
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+            ef_search: 64,
+            seed: 0,
+        }
+    }
+}
+
+/// An approximate nearest neighbour index based on a hierarchical navigable small world graph.
+///
+/// Searching takes roughly logarithmic time in the number of encodings, instead of the linear
+/// time of comparing against every encoding. Removed encodings are only marked as removed, and
+/// the graph is rebuilt once they outnumber the remaining encodings.
+///
+/// See Malkov and Yashunin, "Efficient and robust approximate nearest neighbor search using
+/// Hierarchical Navigable Small World graphs".
+#[derive(Clone, Debug)]
+pub struct HnswIndex {
+    params: HnswParams,
+    nodes: Vec<Node>,
+    entry: Option<u32>,
+    /// The nodes of every key that haven't been removed.
+    keys: HashMap<usize, Vec<u32>>,
+    removed: usize,
+    rng: u64,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    key: usize,
+    values: [f32; FaceEncodingF32::LEN],
+    /// The neighbours on every layer from the lowest up to the layer of the node.
+    neighbors: Vec<Vec<u32>>,
+    removed: bool,
+}
+
+impl HnswIndex {
+    pub fn new(params: HnswParams) -> Self {
+        Self {
+            params,
+            nodes: Vec::new(),
+            entry: None,
+            keys: HashMap::new(),
+            removed: 0,
+            rng: initial_rng(params.seed),
+        }
+    }
+
+    pub fn params(&self) -> &HnswParams {
+        &self.params
+    }
+
+    /// Trade recall for search speed without rebuilding the index.
+    pub fn set_ef_search(&mut self, ef_search: usize) {
+        self.params.ef_search = ef_search;
+    }
+
+    /// Rebuild the graph without the removed encodings.
+    pub fn compact(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+
+        self.entry = None;
+        self.keys.clear();
+        self.removed = 0;
+
+        for node in nodes.into_iter().filter(|node| !node.removed) {
+            self.insert_values(node.key, node.values);
+        }
+    }
+
+    /// Store the index in a file, which is replaced atomically.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PersistenceError> {
+        persistence::write_atomic(path.as_ref(), &self.to_bytes())
+    }
+
+    /// Load an index stored with [`HnswIndex::save`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PersistenceError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Layout (all integers and values little-endian): magic bytes `DFRH`, format version `1`,
+    /// the parameters and state of the random number generator (8 bytes each), the entry node
+    /// (8 bytes, all ones if empty), the number of nodes (8 bytes), the nodes, and the FNV-1a
+    /// checksum of all preceding bytes (8 bytes).
+    ///
+    /// A node is stored as its key (8 bytes), whether it was removed (1 byte), its number of
+    /// layers (1 byte), its values as `f32`, and for every layer the number of neighbours
+    /// (4 bytes) followed by the neighbours (4 bytes each).
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        for value in [
+            self.params.m as u64,
+            self.params.ef_construction as u64,
+            self.params.ef_search as u64,
+            self.params.seed,
+            self.rng,
+            self.entry.map_or(u64::MAX, u64::from),
+            self.nodes.len() as u64,
+        ] {
+            persistence::write_u64(&mut bytes, value);
+        }
+
+        for node in &self.nodes {
+            persistence::write_u64(&mut bytes, node.key as u64);
+            bytes.push(node.removed as u8);
+            bytes.push(node.neighbors.len() as u8);
+            for value in node.values {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            for neighbors in &node.neighbors {
+                bytes.extend_from_slice(&(neighbors.len() as u32).to_le_bytes());
+                for neighbor in neighbors {
+                    bytes.extend_from_slice(&neighbor.to_le_bytes());
+                }
+            }
+        }
+
+        let checksum = persistence::checksum(&bytes);
+        persistence::write_u64(&mut bytes, checksum);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, PersistenceError> {
+        if bytes.len() < 8 {
+            return Err(PersistenceError::Truncated);
+        }
+        let (content, stored) = bytes.split_at(bytes.len() - 8);
+
+        let mut reader = Reader(content);
+        persistence::check_header(&mut reader, MAGIC, VERSION)?;
+        if u64::from_le_bytes(stored.try_into().unwrap()) != persistence::checksum(content) {
+            return Err(PersistenceError::ChecksumMismatch);
+        }
+
+        let params = HnswParams {
+            m: reader.u64()? as usize,
+            ef_construction: reader.u64()? as usize,
+            ef_search: reader.u64()? as usize,
+            seed: reader.u64()?,
+        };
+        let mut index = Self::new(params);
+        index.rng = reader.u64()?;
+
+        let entry = reader.u64()?;
+        let len = reader.u64()?;
+        for id in 0..len {
+            let key = reader.u64()? as usize;
+            let removed = reader.take(1)?[0] != 0;
+            let layers = reader.take(1)?[0] as usize;
+            if layers == 0 {
+                return Err(PersistenceError::Corrupted);
+            }
+
+            let mut values = [0.0; FaceEncodingF32::LEN];
+            for (value, chunk) in values
+                .iter_mut()
+                .zip(reader.take(4 * FaceEncodingF32::LEN)?.chunks_exact(4))
+            {
+                *value = f32::from_le_bytes(chunk.try_into().unwrap());
+            }
+
+            let mut neighbors = Vec::with_capacity(layers);
+            for _ in 0..layers {
+                let count = reader.u32()?;
+                let layer = (0..count)
+                    .map(|_| reader.u32())
+                    .collect::<Result<Vec<_>, _>>()?;
+                if layer.iter().any(|&neighbor| neighbor as u64 >= len) {
+                    return Err(PersistenceError::Corrupted);
+                }
+                neighbors.push(layer);
+            }
+
+            if removed {
+                index.removed += 1;
+            } else {
+                index.keys.entry(key).or_default().push(id as u32);
+            }
+            index.nodes.push(Node {
+                key,
+                values,
+                neighbors,
+                removed,
+            });
+        }
+
+        index.entry = match entry {
+            u64::MAX => None,
+            entry if entry < len => Some(entry as u32),
+            _ => return Err(PersistenceError::Corrupted),
+        };
+
+        if !reader.0.is_empty() || (index.entry.is_none() && len > 0) {
+            return Err(PersistenceError::Corrupted);
+        }
+
+        // the search follows neighbours on the layers they were listed on, starting at the entry
+        let layers = |id: u32| index.nodes[id as usize].neighbors.len();
+        let linked = index.nodes.iter().all(|node| {
+            node.neighbors.iter().enumerate().all(|(layer, neighbors)| {
+                neighbors.iter().all(|&neighbor| layers(neighbor) > layer)
+            })
+        });
+        let highest = index.entry.is_none_or(|entry| {
+            index
+                .nodes
+                .iter()
+                .all(|node| node.neighbors.len() <= layers(entry))
+        });
+        if !linked || !highest {
+            return Err(PersistenceError::Corrupted);
+        }
+
+        Ok(index)
+    }
+
+    fn insert_values(&mut self, key: usize, values: [f32; FaceEncodingF32::LEN]) {
+        let id = self.nodes.len() as u32;
+        let level = self.random_level();
+
+        self.nodes.push(Node {
+            key,
+            values,
+            neighbors: vec![Vec::new(); level + 1],
+            removed: false,
+        });
+        self.keys.entry(key).or_default().push(id);
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(id);
+            return;
+        };
+
+        let top = self.level(entry);
+        let mut current = self.descend(&values, entry, level + 1);
+
+        for layer in (0..=level.min(top)).rev() {
+            let candidates =
+                self.search_layer(&values, current, self.params.ef_construction, layer);
+            let selected = self.select_neighbors(&candidates, self.params.m);
+
+            for &neighbor in &selected {
+                self.nodes[neighbor as usize].neighbors[layer].push(id);
+                self.prune(neighbor, layer);
+            }
+            self.nodes[id as usize].neighbors[layer] = selected;
+
+            current = candidates[0].id;
+        }
+
+        if level > top {
+            self.entry = Some(id);
+        }
+    }
+
+    /// Greedily move towards the query on the layers above `layer`.
+    fn descend(&self, query: &[f32], entry: u32, layer: usize) -> u32 {
+        let mut current = entry;
+        let mut distance = self.distance(query, current);
+
+        for layer in (layer..=self.level(entry)).rev() {
+            let mut changed = true;
+            while changed {
+                changed = false;
+
+                for &neighbor in &self.nodes[current as usize].neighbors[layer] {
+                    let neighbor_distance = self.distance(query, neighbor);
+                    if neighbor_distance < distance {
+                        current = neighbor;
+                        distance = neighbor_distance;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        current
+    }
+
+    /// The `ef` nodes closest to the query on a layer, starting with the closest.
+    fn search_layer(&self, query: &[f32], entry: u32, ef: usize, layer: usize) -> Vec<Candidate> {
+        let entry = Candidate {
+            distance: self.distance(query, entry),
+            id: entry,
+        };
+
+        let mut visited = HashSet::from([entry.id]);
+        let mut candidates = BinaryHeap::from([Reverse(entry)]);
+        let mut results = BinaryHeap::from([entry]);
+
+        while let Some(Reverse(candidate)) = candidates.pop() {
+            if results.len() >= ef && candidate.distance > results.peek().unwrap().distance {
+                break;
+            }
+
+            for &neighbor in &self.nodes[candidate.id as usize].neighbors[layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+
+                let neighbor = Candidate {
+                    distance: self.distance(query, neighbor),
+                    id: neighbor,
+                };
+                if results.len() < ef || neighbor.distance < results.peek().unwrap().distance {
+                    candidates.push(Reverse(neighbor));
+                    results.push(neighbor);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    /// Choose up to `m` neighbours from candidates sorted by distance, preferring candidates
+    /// in different directions over candidates that are closer to an already chosen neighbour.
+    fn select_neighbors(&self, candidates: &[Candidate], m: usize) -> Vec<u32> {
+        let mut selected: Vec<u32> = Vec::with_capacity(m);
+        let mut skipped = Vec::new();
+
+        for candidate in candidates {
+            if selected.len() >= m {
+                break;
+            }
+
+            let values = &self.nodes[candidate.id as usize].values;
+            if selected
+                .iter()
+                .all(|&other| self.distance(values, other) > candidate.distance)
+            {
+                selected.push(candidate.id);
+            } else {
+                skipped.push(candidate.id);
+            }
+        }
+
+        let missing = m.saturating_sub(selected.len());
+        selected.extend(skipped.into_iter().take(missing));
+
+        selected
+    }
+
+    /// Drop the worst neighbours of a node that has too many on a layer.
+    fn prune(&mut self, id: u32, layer: usize) {
+        let max = if layer == 0 {
+            2 * self.params.m
+        } else {
+            self.params.m
+        };
+
+        let node = &self.nodes[id as usize];
+        if node.neighbors[layer].len() <= max {
+            return;
+        }
+
+        let mut candidates: Vec<_> = node.neighbors[layer]
+            .iter()
+            .map(|&neighbor| Candidate {
+                distance: self.distance(&node.values, neighbor),
+                id: neighbor,
+            })
+            .collect();
+        candidates.sort_unstable();
+
+        self.nodes[id as usize].neighbors[layer] = self.select_neighbors(&candidates, max);
+    }
+
+    fn distance(&self, query: &[f32], id: u32) -> f32 {
+        squared_distance(query, &self.nodes[id as usize].values)
+    }
+
+    fn level(&self, id: u32) -> usize {
+        self.nodes[id as usize].neighbors.len() - 1
+    }
+
+    /// Draw the layer of a new node from an exponentially decaying distribution.
+    fn random_level(&mut self) -> usize {
+        // xorshift64*
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let random = self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d);
+
+        // uniformly distributed in (0, 1]
+        let uniform = 1.0 - (random >> 11) as f64 / (1u64 << 53) as f64;
+        let multiplier = 1.0 / (self.params.m.max(2) as f64).ln();
+
+        ((-uniform.ln() * multiplier) as usize).min(MAX_LEVEL)
+    }
+}
+
+impl Default for HnswIndex {
+    fn default() -> Self {
+        Self::new(HnswParams::default())
+    }
+}
+
+impl SearchIndex for HnswIndex {
+    fn insert(&mut self, key: usize, encoding: &FaceEncodingF32) {
+        self.insert_values(key, **encoding);
+    }
+
+    fn remove(&mut self, key: usize) {
+        let Some(ids) = self.keys.remove(&key) else {
+            return;
+        };
+
+        for &id in &ids {
+            self.nodes[id as usize].removed = true;
+        }
+        self.removed += ids.len();
+
+        if self.removed > self.len() {
+            self.compact();
+        }
+    }
+
+    fn search(&self, query: &FaceEncodingF32, k: usize) -> Vec<(usize, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+
+        let current = self.descend(query.as_ref(), entry, 1);
+        let candidates =
+            self.search_layer(query.as_ref(), current, self.params.ef_search.max(k), 0);
+
+        let mut seen = HashSet::new();
+        candidates
+            .into_iter()
+            .map(|candidate| (&self.nodes[candidate.id as usize], candidate.distance))
+            .filter(|(node, _)| !node.removed && seen.insert(node.key))
+            .map(|(node, distance)| (node.key, distance.sqrt()))
+            .take(k)
+            .collect()
+    }
+
+    fn count(&self, key: usize) -> usize {
+        self.keys.get(&key).map_or(0, Vec::len)
+    }
+
+    fn len(&self) -> usize {
+        self.nodes.len() - self.removed
+    }
+
+    fn clear(&mut self) {
+        *self = Self::new(self.params);
+    }
+}
+
+/// A node along with its squared distance to the query, ordered by distance.
+#[derive(Copy, Clone, Debug)]
+struct Candidate {
+    distance: f32,
+    id: u32,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+fn initial_rng(seed: u64) -> u64 {
+    // xorshift requires a non-zero state
+    (seed ^ 0x9e37_79b9_7f4a_7c15).max(1)
+}
+
+#[test]
+fn hnsw_test() {
+    use super::index::{BruteForceIndex, measure_recall};
+
+    // deterministic pseudo random encodings
+    let mut state = 1u64;
+    let mut random = move || {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (state >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+    };
+    let encodings: Vec<FaceEncodingF32> = (0..500)
+        .map(|_| FaceEncodingF32::new(std::array::from_fn(|_| random())))
+        .collect();
+
+    let mut index = HnswIndex::new(HnswParams {
+        m: 8,
+        ef_construction: 64,
+        ..Default::default()
+    });
+    let mut exact = BruteForceIndex::new();
+    for (key, encoding) in encodings.iter().enumerate() {
+        index.insert(key, encoding);
+        exact.insert(key, encoding);
+    }
+
+    let recall = measure_recall(&index, &exact, &encodings[..50], 10);
+    assert!(recall > 0.9, "recall {recall}");
+
+    let loaded = HnswIndex::from_bytes(&index.to_bytes()).unwrap();
+    assert_eq!(
+        loaded.search(&encodings[7], 5),
+        index.search(&encodings[7], 5)
+    );
+
+    for key in 0..400 {
+        index.remove(key);
+    }
+    assert_eq!(index.len(), 100);
+    assert!(
+        index
+            .search(&encodings[3], 10)
+            .iter()
+            .all(|&(key, _)| key >= 400)
+    );
+    index.compact();
+    assert_eq!(index.nodes.len(), 100);
+    assert_eq!(index.search(&encodings[450], 1), vec![(450, 0.0)]);
+
+    let mut bytes = index.to_bytes();
+    bytes[20] ^= 1;
+    assert!(matches!(
+        HnswIndex::from_bytes(&bytes),
+        Err(PersistenceError::ChecksumMismatch)
+    ));
+
+    // files with a valid checksum but an inconsistent graph are rejected instead of panicking when searched
+    let node = |neighbors: Vec<Vec<u32>>| Node {
+        key: 0,
+        values: [0.0; FaceEncodingF32::LEN],
+        neighbors,
+        removed: false,
+    };
+    let mut crafted = HnswIndex::new(HnswParams::default());
+    crafted.nodes = vec![node(vec![vec![1], vec![1]]), node(vec![vec![0]])];
+    crafted.entry = Some(0);
+    assert!(matches!(
+        HnswIndex::from_bytes(&crafted.to_bytes()),
+        Err(PersistenceError::Corrupted)
+    ));
+
+    crafted.nodes[0].neighbors[1].clear();
+    let mut bytes = crafted.to_bytes();
+    assert!(HnswIndex::from_bytes(&bytes).is_ok());
+
+    // point the entry at the second node, which has fewer layers than the first one
+    bytes[45..53].copy_from_slice(&1u64.to_le_bytes());
+    let content = bytes.len() - 8;
+    let checksum = persistence::checksum(&bytes[..content]);
+    bytes[content..].copy_from_slice(&checksum.to_le_bytes());
+    assert!(matches!(
+        HnswIndex::from_bytes(&bytes),
+        Err(PersistenceError::Corrupted)
+    ));
+}
@@ -0,0 +1,130 @@
+use std::collections::{HashMap, HashSet};
+
+use super::encoding_f32::FaceEncodingF32;
+use super::simd;
+
+/// Finds the stored encodings closest to a face, as used by [`FaceComparer::with_index`](super::FaceComparer::with_index).
+///
+/// Encodings are stored under the keys of a [`FaceComparer`](super::FaceComparer),
+/// where a key may hold several encodings, one for every enrollment photo.
+pub trait SearchIndex {
+    /// Store an encoding under a key, next to the encodings already stored under it.
+    fn insert(&mut self, key: usize, encoding: &FaceEncodingF32);
+
+    /// Remove all encodings stored under a key.
+    fn remove(&mut self, key: usize);
+
+    /// The (approximately) `k` closest keys, along with the euclidean distance to their closest
+    /// encoding, starting with the closest.
+    fn search(&self, query: &FaceEncodingF32, k: usize) -> Vec<(usize, f32)>;
+
+    /// The number of encodings stored under a key.
+    fn count(&self, key: usize) -> usize;
+
+    /// The number of stored encodings.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove all encodings.
+    fn clear(&mut self);
+}
+
+/// An exact index comparing the query against every stored encoding.
+///
+/// Useful as a reference for measuring the recall of an approximate index.
+#[derive(Clone, Debug, Default)]
+pub struct BruteForceIndex {
+    encodings: HashMap<usize, Vec<[f32; FaceEncodingF32::LEN]>>,
+    len: usize,
+}
+
+impl BruteForceIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SearchIndex for BruteForceIndex {
+    fn insert(&mut self, key: usize, encoding: &FaceEncodingF32) {
+        self.encodings.entry(key).or_default().push(**encoding);
+        self.len += 1;
+    }
+
+    fn remove(&mut self, key: usize) {
+        if let Some(encodings) = self.encodings.remove(&key) {
+            self.len -= encodings.len();
+        }
+    }
+
+    fn search(&self, query: &FaceEncodingF32, k: usize) -> Vec<(usize, f32)> {
+        let mut ranked: Vec<_> = self
+            .encodings
+            .iter()
+            .map(|(&key, encodings)| {
+                let distance = encodings
+                    .iter()
+                    .map(|values| squared_distance(query.as_ref(), values))
+                    .fold(f32::INFINITY, f32::min);
+
+                (key, distance.sqrt())
+            })
+            .collect();
+        ranked.sort_unstable_by(|(_, x), (_, y)| x.total_cmp(y));
+        ranked.truncate(k);
+
+        ranked
+    }
+
+    fn count(&self, key: usize) -> usize {
+        self.encodings.get(&key).map_or(0, Vec::len)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn clear(&mut self) {
+        self.encodings.clear();
+        self.len = 0;
+    }
+}
+
+/// The fraction of the `k` closest keys found by an exact index, such as [`BruteForceIndex`],
+/// that an approximate or quantized index finds as well, averaged over the queries.
+pub fn measure_recall(
+    index: &dyn SearchIndex,
+    exact: &dyn SearchIndex,
+    queries: &[FaceEncodingF32],
+    k: usize,
+) -> f64 {
+    let mut expected = 0;
+    let mut found = 0;
+
+    for query in queries {
+        let closest: HashSet<_> = exact
+            .search(query, k)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        expected += closest.len();
+        found += index
+            .search(query, k)
+            .into_iter()
+            .filter(|(key, _)| closest.contains(key))
+            .count();
+    }
+
+    if expected == 0 {
+        1.0
+    } else {
+        found as f64 / expected as f64
+    }
+}
+
+/// The squared euclidean distance, which orders encodings like the euclidean distance.
+pub(crate) fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    simd::squared_distance(a, b)
+}
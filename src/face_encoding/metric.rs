@@ -0,0 +1,104 @@
+/// How the distance between two face encodings is measured.
+///
+/// Every metric comes with its own recommended threshold, see [`Metric::threshold`].
+/// Two encodings whose distance is at most the threshold are considered to belong to the same face.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Metric {
+    /// The euclidean distance, which is what dlib's encodings were trained for.
+    ///
+    /// Recommended threshold: `0.6`.
+    #[default]
+    Euclidean,
+    /// The squared euclidean distance, which orders encodings the same way as [`Metric::Euclidean`]
+    /// but skips the square root.
+    ///
+    /// Recommended threshold: `0.36`.
+    SquaredEuclidean,
+    /// One minus the cosine similarity, which ranges from `0.0` for encodings pointing in the same
+    /// direction to `2.0` for opposite encodings.
+    ///
+    /// Recommended threshold: `0.18`, which matches the euclidean threshold for unit-length encodings.
+    Cosine,
+    /// The euclidean distance between the encodings after scaling them to unit length.
+    ///
+    /// Recommended threshold: `0.6`, which matches the euclidean threshold for unit-length encodings.
+    NormalizedEuclidean,
+}
+
+impl Metric {
+    /// The distance between two encodings, given as slices of equal length.
+    pub fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        debug_assert_eq!(a.len(), b.len());
+
+        self.distance_of(a.iter().copied().zip(b.iter().copied()))
+    }
+
+    /// The distance between two single precision encodings, given as slices of equal length.
+    pub fn distance_f32(&self, a: &[f32], b: &[f32]) -> f32 {
+        debug_assert_eq!(a.len(), b.len());
+
+        self.distance_of(a.iter().zip(b).map(|(&a, &b)| (a as f64, b as f64))) as f32
+    }
+
+    /// The recommended threshold for deciding whether two encodings belong to the same face.
+    ///
+    /// These are starting points for dlib's face recognition model. They are not guaranteed to be
+    /// accurate for every population and camera, so calibrate them on your own data where possible,
+    /// see [`Calibration`](super::Calibration).
+    pub fn threshold(&self) -> f64 {
+        match self {
+            Self::Euclidean => 0.6,
+            Self::SquaredEuclidean => 0.36,
+            Self::Cosine => 0.18,
+            Self::NormalizedEuclidean => 0.6,
+        }
+    }
+
+    fn distance_of(&self, values: impl Iterator<Item = (f64, f64)>) -> f64 {
+        match self {
+            Self::Euclidean => squared_euclidean(values).sqrt(),
+            Self::SquaredEuclidean => squared_euclidean(values),
+            Self::Cosine => 1.0 - cosine_similarity(values),
+            // |a/|a| - b/|b||^2 = 2 - 2 cos(a, b)
+            Self::NormalizedEuclidean => (2.0 - 2.0 * cosine_similarity(values)).max(0.0).sqrt(),
+        }
+    }
+}
+
+fn squared_euclidean(values: impl Iterator<Item = (f64, f64)>) -> f64 {
+    values.map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// The cosine similarity of two vectors, where a zero vector is treated as orthogonal to everything.
+fn cosine_similarity(values: impl Iterator<Item = (f64, f64)>) -> f64 {
+    let (dot, norm_a, norm_b) = values.fold((0.0, 0.0, 0.0), |(dot, norm_a, norm_b), (a, b)| {
+        (dot + a * b, norm_a + a * a, norm_b + b * b)
+    });
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        (dot / (norm_a * norm_b).sqrt()).clamp(-1.0, 1.0)
+    }
+}
+
+#[test]
+fn metric_test() {
+    let a = [3.0, 0.0];
+    let b = [0.0, 4.0];
+
+    assert_eq!(Metric::Euclidean.distance(&a, &b), 5.0);
+    assert_eq!(Metric::SquaredEuclidean.distance(&a, &b), 25.0);
+    assert_eq!(Metric::Cosine.distance(&a, &b), 1.0);
+    assert_eq!(Metric::NormalizedEuclidean.distance(&a, &b), 2.0_f64.sqrt());
+
+    assert_eq!(Metric::Cosine.distance(&a, &[6.0, 0.0]), 0.0);
+    assert_eq!(Metric::NormalizedEuclidean.distance(&a, &[6.0, 0.0]), 0.0);
+    assert_eq!(Metric::Cosine.distance(&a, &[0.0, 0.0]), 1.0);
+
+    assert_eq!(
+        Metric::Euclidean.distance_f32(&[3.0, 0.0], &[0.0, 4.0]),
+        5.0
+    );
+}
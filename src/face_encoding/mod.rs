@@ -1,13 +1,40 @@
 //! Face encoding structs.
 
 mod base;
+mod calibration;
 mod compare;
 mod encoding;
+mod encoding_f32;
 mod encodings;
+mod fingerprint;
+mod flat;
+mod gallery;
+mod hnsw;
+mod index;
+mod metric;
 mod nn;
+mod options;
+pub mod persistence;
+pub mod serialization;
+mod shared;
+mod simd;
+mod template;
 
 pub use self::base::FaceEncoderTrait;
-pub use self::compare::FaceComparer;
-pub use self::encoding::FaceEncoding;
+pub use self::calibration::{Calibration, CalibrationError, ErrorRates};
+pub use self::compare::{FaceComparer, FaceMatch};
+pub use self::encoding::{ArraySizeError, FaceEncoding};
+pub use self::encoding_f32::FaceEncodingF32;
 pub use self::encodings::FaceEncodings;
-pub use self::nn::FaceEncoderNetwork;
+pub use self::fingerprint::{FingerprintMismatch, ModelFingerprint, ParseFingerprintError};
+pub use self::flat::{FlatIndex, Quantization};
+pub use self::gallery::{Gallery, GalleryMatch};
+pub use self::hnsw::{HnswIndex, HnswParams};
+pub use self::index::{BruteForceIndex, SearchIndex, measure_recall};
+pub use self::metric::Metric;
+pub use self::nn::{ChipSizeError, FaceEncoderNetwork};
+pub use self::options::{Averaging, EncodingOptions, EncodingOptionsError, JitterOptions};
+pub use self::persistence::{PersistenceError, PersistentComparer};
+pub use self::serialization::{DecodeError, ENCODING_MODEL_ID};
+pub use self::shared::{GalleryEvent, SharedGallery};
+pub use self::template::{IdentityTemplate, Scoring};
@@ -1,8 +1,15 @@
+use std::fmt;
+use std::ops::Deref;
 use std::path::Path;
+use std::slice;
 
 use super::base::FaceEncoderTrait;
+use super::encoding_f32::FaceEncodingF32;
 use super::encodings::FaceEncodings;
+use super::fingerprint::ModelFingerprint;
+use super::options::{EncodingOptions, EncodingOptionsError};
 use crate::base::path_as_cstring;
+use crate::face_alignment::FaceChip;
 use crate::landmark_prediction::FaceLandmarks;
 use crate::matrix::ImageMatrix;
 
@@ -10,6 +17,9 @@ use crate::matrix::ImageMatrix;
 #[derive(Clone)]
 pub struct FaceEncoderNetwork {
     inner: FaceEncoderNetworkInner,
+    options: EncodingOptions,
+    /// Hash of the deserialized network, see [`FaceEncoderNetwork::fingerprint`].
+    network_hash: u64,
     /// Loss layers don't specify whether thei are thread safe, so we asume they
     /// need to be held behind a mutex as stated in the dlib
     /// [documentation](http://dlib.net/intro.html)
@@ -23,6 +33,9 @@ pub struct FaceEncoderNetwork {
 cpp_class!(unsafe struct FaceEncoderNetworkInner as "face_encoding_nn");
 
 impl FaceEncoderNetwork {
+    /// The number of face chips passed through the network at once, unless specified otherwise.
+    pub const DEFAULT_BATCH_SIZE: usize = 16;
+
     #[cfg(feature = "embed-fe-nn")]
     pub fn default() -> Result<Self, String> {
         use crate::embed::{ModelFile, check_file_or_download};
@@ -62,66 +75,332 @@ impl FaceEncoderNetwork {
                 filename.as_ref().display()
             ))
         } else {
+            let network_hash = unsafe {
+                let network = &inner;
+
+                cpp!([network as "face_encoding_nn*"] -> u64 as "uint64_t" {
+                    return hash_network(*network);
+                })
+            };
+
             Ok(Self {
                 inner,
+                options: EncodingOptions::default(),
+                network_hash,
                 data: std::marker::PhantomData::default(),
             })
         }
     }
+
+    /// Use different settings for generating encodings, unless they are out of range.
+    pub fn with_options(mut self, options: EncodingOptions) -> Result<Self, EncodingOptionsError> {
+        self.set_options(options)?;
+        Ok(self)
+    }
+
+    /// The settings used for generating encodings.
+    pub fn options(&self) -> &EncodingOptions {
+        &self.options
+    }
+
+    /// Like [`FaceEncoderNetwork::with_options`], keeping the current settings if the new ones are out of range.
+    pub fn set_options(&mut self, options: EncodingOptions) -> Result<(), EncodingOptionsError> {
+        options.validate()?;
+        self.options = options;
+        Ok(())
+    }
+
+    /// Identifies the network and the options the encodings of this encoder are generated with.
+    ///
+    /// Every [`FaceEncoding`](super::FaceEncoding) generated by this encoder is tagged with this fingerprint,
+    /// and comparing encodings with different fingerprints using the `try_` methods fails.
+    pub fn fingerprint(&self) -> ModelFingerprint {
+        ModelFingerprint::new(self.network_hash, &self.options)
+    }
 }
 
-impl FaceEncoderTrait for FaceEncoderNetwork {
-    fn get_face_encodings(
+impl FaceEncoderNetwork {
+    /// Like [`FaceEncoderTrait::get_face_encodings`], but without converting the encodings to `f64`.
+    ///
+    /// The encodings are not tagged, so keep the [`fingerprint`](FaceEncoderNetwork::fingerprint) of the encoder along with them.
+    pub fn get_face_encodings_f32(
         &self,
         image: &ImageMatrix,
         landmarks: &[FaceLandmarks],
         num_jitters: u32,
+    ) -> Vec<FaceEncodingF32> {
+        let chips = self.extract_face_chips(image, landmarks);
+
+        self.encode_face_chips_f32(&chips, num_jitters, Self::DEFAULT_BATCH_SIZE)
+    }
+
+    /// Get face encodings from face chips that have already been aligned, skipping detection and landmarking.
+    ///
+    /// The chips need to be aligned and cropped the same way [`FaceChip`](crate::FaceChip) does with its
+    /// default size and padding, which means every chip has to be 150x150.
+    pub fn get_face_encodings_from_chips(
+        &self,
+        chips: &[ImageMatrix],
+        num_jitters: u32,
+    ) -> Result<FaceEncodings, ChipSizeError> {
+        check_chip_sizes(chips)?;
+
+        Ok(self.encode_face_chips(chips, num_jitters, Self::DEFAULT_BATCH_SIZE))
+    }
+
+    /// Like [`FaceEncoderNetwork::get_face_encodings_from_chips`], but without converting the encodings to `f64`.
+    pub fn get_face_encodings_from_chips_f32(
+        &self,
+        chips: &[ImageMatrix],
+        num_jitters: u32,
+    ) -> Result<Vec<FaceEncodingF32>, ChipSizeError> {
+        check_chip_sizes(chips)?;
+
+        Ok(self.encode_face_chips_f32(chips, num_jitters, Self::DEFAULT_BATCH_SIZE))
+    }
+
+    /// Get face encodings for faces spread over many images, such as one face per enrollment photo.
+    ///
+    /// The faces of all images are passed through the network together, `batch_size` chips at a time.
+    /// A larger batch size is faster, but uses more memory.
+    pub fn get_face_encodings_batch(
+        &self,
+        faces: &[(&ImageMatrix, &FaceLandmarks)],
+        num_jitters: u32,
+        batch_size: usize,
     ) -> FaceEncodings {
+        let chips = self.extract_face_chips_batch(faces);
+
+        self.encode_face_chips(&chips, num_jitters, batch_size)
+    }
+
+    /// Like [`FaceEncoderNetwork::get_face_encodings_batch`], but without converting the encodings to `f64`.
+    pub fn get_face_encodings_batch_f32(
+        &self,
+        faces: &[(&ImageMatrix, &FaceLandmarks)],
+        num_jitters: u32,
+        batch_size: usize,
+    ) -> Vec<FaceEncodingF32> {
+        let chips = self.extract_face_chips_batch(faces);
+
+        self.encode_face_chips_f32(&chips, num_jitters, batch_size)
+    }
+
+    fn extract_face_chips(&self, image: &ImageMatrix, landmarks: &[FaceLandmarks]) -> FaceChips {
         let num_faces = landmarks.len();
         let landmarks = landmarks.as_ptr();
-        let net = &self.inner;
+        let chip_size = FaceChip::DEFAULT_SIZE;
+        let padding = self.options.padding;
 
         unsafe {
             cpp!([
-                    net as "face_encoding_nn*",
-                    image as "dlib::matrix<dlib::rgb_pixel>*",
-                    landmarks as "dlib::full_object_detection*",
+                    image as "const dlib::matrix<dlib::rgb_pixel>*",
+                    landmarks as "const dlib::full_object_detection*",
                     num_faces as "size_t",
-                    num_jitters as "uint32_t"
-                ] -> FaceEncodings as "std::vector<dlib::matrix<double,0,1>>" {
-                std::vector<dlib::matrix<double,0,1>> encodings;
-                encodings.reserve(num_faces);
-
+                    chip_size as "size_t",
+                    padding as "double"
+                ] -> FaceChips as "std::vector<dlib::matrix<dlib::rgb_pixel>>" {
                 // first we need to use the landmarks to get image chips for each face
 
-                std::vector<dlib::chip_details> dets;
-                dets.reserve(num_faces);
+                std::vector<dlib::matrix<dlib::rgb_pixel>> face_chips(num_faces);
+                for (size_t offset = 0; offset < num_faces; offset++) {
+                    dlib::chip_details details = dlib::get_face_chip_details(*(landmarks + offset), chip_size, padding);
+                    dlib::extract_image_chip(*image, details, face_chips[offset]);
+                }
+
+                return face_chips;
+            })
+        }
+    }
+
+    fn extract_face_chips_batch(&self, faces: &[(&ImageMatrix, &FaceLandmarks)]) -> FaceChips {
+        let num_faces = faces.len();
+        let images: Vec<*const ImageMatrix> =
+            faces.iter().map(|(image, _)| *image as *const _).collect();
+        let landmarks: Vec<*const FaceLandmarks> = faces
+            .iter()
+            .map(|(_, landmarks)| *landmarks as *const _)
+            .collect();
+        let (images, landmarks) = (images.as_ptr(), landmarks.as_ptr());
+        let chip_size = FaceChip::DEFAULT_SIZE;
+        let padding = self.options.padding;
 
-                dlib::array<dlib::matrix<dlib::rgb_pixel>> face_chips;
+        unsafe {
+            cpp!([
+                    images as "const dlib::matrix<dlib::rgb_pixel>* const*",
+                    landmarks as "const dlib::full_object_detection* const*",
+                    num_faces as "size_t",
+                    chip_size as "size_t",
+                    padding as "double"
+                ] -> FaceChips as "std::vector<dlib::matrix<dlib::rgb_pixel>>" {
+                // every face may come from a different image, so the chips are extracted one by one
+
+                std::vector<dlib::matrix<dlib::rgb_pixel>> face_chips(num_faces);
                 for (size_t offset = 0; offset < num_faces; offset++) {
-                    dlib::chip_details details = dlib::get_face_chip_details(*(landmarks + offset), 150, 0.25);
-                    dets.push_back(details);
+                    dlib::chip_details details = dlib::get_face_chip_details(*landmarks[offset], chip_size, padding);
+                    dlib::extract_image_chip(*images[offset], details, face_chips[offset]);
                 }
-                dlib::extract_image_chips(*image, dets, face_chips);
-
-                // extract descriptors and convert from float vectors to double vectors
-
-                if (num_jitters <= 1) {
-                    auto network_output = (*net)(face_chips, 16);
-                    for (auto& float_encoding : network_output) {
-                        encodings.push_back((dlib::matrix_cast<double>(float_encoding)));
-                    }
-                } else {
-                    for (auto& chip : face_chips) {
-                        auto network_output = (*net)(jitter_image(chip, num_jitters), 16);
-                        dlib::matrix<float,0,1> float_encoding = dlib::mean(dlib::mat(network_output));
-
-                        encodings.push_back(dlib::matrix_cast<double>(float_encoding));
-                    }
+
+                return face_chips;
+            })
+        }
+    }
+
+    fn encode_face_chips(
+        &self,
+        chips: &[ImageMatrix],
+        num_jitters: u32,
+        batch_size: usize,
+    ) -> FaceEncodings {
+        let num_chips = chips.len();
+        let chips = chips.as_ptr();
+        let batch_size = batch_size.max(1);
+        let jitter = &self.options.jitter;
+        let averaging = self.options.averaging as u32;
+        let seed = self.options.seed;
+        let flip = self.options.flip;
+        let fingerprint = self.fingerprint().as_u64();
+        let net = &self.inner;
+
+        unsafe {
+            cpp!([
+                    net as "face_encoding_nn*",
+                    chips as "const dlib::matrix<dlib::rgb_pixel>*",
+                    num_chips as "size_t",
+                    num_jitters as "uint32_t",
+                    batch_size as "size_t",
+                    jitter as "const jitter_options*",
+                    averaging as "uint32_t",
+                    seed as "uint64_t",
+                    flip as "bool",
+                    fingerprint as "uint64_t"
+                ] -> FaceEncodings as "std::vector<face_encoding>" {
+                return cast_encodings(encode_face_chips(*net, chips, num_chips, num_jitters, batch_size, *jitter, averaging, seed, flip), fingerprint);
+            })
+        }
+    }
+
+    fn encode_face_chips_f32(
+        &self,
+        chips: &[ImageMatrix],
+        num_jitters: u32,
+        batch_size: usize,
+    ) -> Vec<FaceEncodingF32> {
+        let num_chips = chips.len();
+        let chips = chips.as_ptr();
+        let batch_size = batch_size.max(1);
+        let jitter = &self.options.jitter;
+        let averaging = self.options.averaging as u32;
+        let seed = self.options.seed;
+        let flip = self.options.flip;
+        let net = &self.inner;
+
+        let len = FaceEncodingF32::LEN;
+        let mut values = vec![0.0f32; num_chips * len];
+        let out = values.as_mut_ptr();
+
+        unsafe {
+            cpp!([
+                    net as "face_encoding_nn*",
+                    chips as "const dlib::matrix<dlib::rgb_pixel>*",
+                    num_chips as "size_t",
+                    num_jitters as "uint32_t",
+                    batch_size as "size_t",
+                    jitter as "const jitter_options*",
+                    averaging as "uint32_t",
+                    seed as "uint64_t",
+                    flip as "bool",
+                    out as "float*",
+                    len as "size_t"
+                ] {
+                auto float_encodings = encode_face_chips(*net, chips, num_chips, num_jitters, batch_size, *jitter, averaging, seed, flip);
+
+                for (size_t offset = 0; offset < num_chips; offset++) {
+                    std::copy(float_encodings[offset].begin(), float_encodings[offset].end(), out + offset * len);
                 }
+            })
+        }
+
+        values
+            .chunks_exact(len)
+            .map(|values| FaceEncodingF32::from_slice(values).unwrap())
+            .collect()
+    }
+}
+
+impl FaceEncoderTrait for FaceEncoderNetwork {
+    fn get_face_encodings(
+        &self,
+        image: &ImageMatrix,
+        landmarks: &[FaceLandmarks],
+        num_jitters: u32,
+    ) -> FaceEncodings {
+        let chips = self.extract_face_chips(image, landmarks);
+
+        self.encode_face_chips(&chips, num_jitters, Self::DEFAULT_BATCH_SIZE)
+    }
+}
+
+cpp_class!(unsafe struct FaceChips as "std::vector<dlib::matrix<dlib::rgb_pixel>>");
 
-                return encodings;
+impl Deref for FaceChips {
+    type Target = [ImageMatrix];
+
+    fn deref(&self) -> &Self::Target {
+        let len = unsafe {
+            cpp!([self as "std::vector<dlib::matrix<dlib::rgb_pixel>>*"] -> usize as "size_t" {
+                return self->size();
             })
+        };
+
+        if len == 0 {
+            &[]
+        } else {
+            unsafe {
+                let pointer = cpp!([self as "std::vector<dlib::matrix<dlib::rgb_pixel>>*"] -> *const ImageMatrix as "dlib::matrix<dlib::rgb_pixel>*" {
+                    return &(*self)[0];
+                });
+
+                slice::from_raw_parts(pointer, len)
+            }
         }
     }
 }
+
+fn check_chip_sizes(chips: &[ImageMatrix]) -> Result<(), ChipSizeError> {
+    match chips.iter().enumerate().find(|(_, chip)| {
+        chip.width() != FaceChip::DEFAULT_SIZE || chip.height() != FaceChip::DEFAULT_SIZE
+    }) {
+        Some((index, chip)) => Err(ChipSizeError {
+            index,
+            width: chip.width(),
+            height: chip.height(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// A face chip passed to the face encoding network does not have the size the network expects.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChipSizeError {
+    /// The position of the offending chip.
+    pub index: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl fmt::Display for ChipSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Face chip {} is {}x{}, but the face encoding network expects {}x{} chips.",
+            self.index,
+            self.width,
+            self.height,
+            FaceChip::DEFAULT_SIZE,
+            FaceChip::DEFAULT_SIZE
+        )
+    }
+}
+
+impl std::error::Error for ChipSizeError {}
@@ -0,0 +1,197 @@
+use std::fmt;
+
+use crate::face_alignment::FaceChip;
+
+/// Settings used by the face encoding network to turn faces into encodings.
+///
+/// Encodings are only comparable when they were generated with the same options.
+/// The defaults match the settings of dlib's own face recognition tools.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncodingOptions {
+    /// The amount of border added around a face when extracting its chip, relative to the face size.
+    ///
+    /// This is ignored for chips that are encoded directly, as they are already cropped.
+    pub padding: f64,
+    /// How chips are randomly perturbed when encoding with `num_jitters > 1`.
+    pub jitter: JitterOptions,
+    /// How the encodings of the jittered chips are combined into a single encoding.
+    pub averaging: Averaging,
+    /// Also encode the horizontal mirror image of every chip (and every jittered chip), and combine
+    /// the encodings of both using `averaging`.
+    ///
+    /// Unlike jittering this is deterministic, and only doubles the cost of encoding a face.
+    pub flip: bool,
+    /// The seed of the random number generator used for jittering.
+    ///
+    /// Every face is jittered with a fresh generator, so encoding the same face with the same
+    /// seed always results in the same encoding, regardless of the thread or the other faces in the batch.
+    pub seed: u64,
+}
+
+impl Default for EncodingOptions {
+    fn default() -> Self {
+        Self {
+            padding: FaceChip::DEFAULT_PADDING,
+            jitter: JitterOptions::default(),
+            averaging: Averaging::Mean,
+            flip: false,
+            seed: 0,
+        }
+    }
+}
+
+impl EncodingOptions {
+    /// Check that the padding and the jitter ranges are usable, as done by
+    /// [`FaceEncoderNetwork::with_options`](super::FaceEncoderNetwork::with_options).
+    pub fn validate(&self) -> Result<(), EncodingOptionsError> {
+        let jitter = &self.jitter;
+        let non_negative = |value: f64| value >= 0.0 && value.is_finite();
+
+        if !non_negative(self.padding) {
+            return Err(EncodingOptionsError::Padding);
+        }
+        if !non_negative(jitter.max_rotation_degrees) {
+            return Err(EncodingOptionsError::Rotation);
+        }
+        if !(jitter.min_scale > 0.0
+            && jitter.min_scale <= jitter.max_scale
+            && jitter.max_scale.is_finite())
+        {
+            return Err(EncodingOptionsError::Scale);
+        }
+        if !non_negative(jitter.max_translation) {
+            return Err(EncodingOptionsError::Translation);
+        }
+        if !(0.0..=1.0).contains(&jitter.flip_probability) {
+            return Err(EncodingOptionsError::FlipProbability);
+        }
+
+        Ok(())
+    }
+}
+
+/// The ranges of the random perturbations applied to a face chip when jittering it,
+/// see [dlib's `jitter_image`](http://dlib.net/imaging.html#jitter_image).
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct JitterOptions {
+    /// The maximum rotation of the chip, in degrees, in either direction.
+    pub max_rotation_degrees: f64,
+    /// The minimum size of the face relative to the chip.
+    pub min_scale: f64,
+    /// The maximum size of the face relative to the chip.
+    pub max_scale: f64,
+    /// The maximum shift of the chip, relative to its size, in either direction.
+    pub max_translation: f64,
+    /// The probability of mirroring the chip horizontally.
+    pub flip_probability: f64,
+}
+
+impl Default for JitterOptions {
+    fn default() -> Self {
+        Self {
+            max_rotation_degrees: 3.0,
+            min_scale: 0.97,
+            max_scale: 0.99999,
+            max_translation: 0.02,
+            flip_probability: 0.5,
+        }
+    }
+}
+
+/// How multiple encodings of the same face are combined.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u32)]
+pub enum Averaging {
+    /// The element-wise mean of the encodings.
+    #[default]
+    Mean = 0,
+    /// The element-wise median of the encodings, which is less sensitive to badly jittered chips.
+    Median = 1,
+}
+
+/// A setting of [`EncodingOptions`] is out of range.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EncodingOptionsError {
+    /// The padding is negative or not finite.
+    Padding,
+    /// The maximum rotation is negative or not finite.
+    Rotation,
+    /// The minimum scale is not positive, exceeds the maximum scale, or the scales are not finite.
+    Scale,
+    /// The maximum translation is negative or not finite.
+    Translation,
+    /// The flip probability is not between `0.0` and `1.0`.
+    FlipProbability,
+}
+
+impl fmt::Display for EncodingOptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Padding => write!(f, "The padding has to be a non-negative number."),
+            Self::Rotation => write!(f, "The maximum rotation has to be a non-negative number."),
+            Self::Scale => write!(
+                f,
+                "The jitter scales have to be positive, with the minimum not above the maximum."
+            ),
+            Self::Translation => {
+                write!(
+                    f,
+                    "The maximum translation has to be a non-negative number."
+                )
+            }
+            Self::FlipProbability => write!(f, "The flip probability has to be between 0 and 1."),
+        }
+    }
+}
+
+impl std::error::Error for EncodingOptionsError {}
+
+#[test]
+fn encoding_options_test() {
+    assert_eq!(EncodingOptions::default().validate(), Ok(()));
+
+    let padding = |padding| EncodingOptions {
+        padding,
+        ..Default::default()
+    };
+    assert_eq!(padding(0.0).validate(), Ok(()));
+    assert_eq!(padding(-0.1).validate(), Err(EncodingOptionsError::Padding));
+    assert_eq!(
+        padding(f64::NAN).validate(),
+        Err(EncodingOptionsError::Padding)
+    );
+
+    let jitter = |jitter| EncodingOptions {
+        jitter,
+        ..Default::default()
+    };
+    let defaults = JitterOptions::default();
+    assert_eq!(
+        jitter(JitterOptions {
+            min_scale: 1.1,
+            ..defaults
+        })
+        .validate(),
+        Err(EncodingOptionsError::Scale)
+    );
+    assert_eq!(
+        jitter(JitterOptions {
+            max_translation: f64::INFINITY,
+            ..defaults
+        })
+        .validate(),
+        Err(EncodingOptionsError::Translation)
+    );
+    assert_eq!(
+        jitter(JitterOptions {
+            flip_probability: f64::NAN,
+            ..defaults
+        })
+        .validate(),
+        Err(EncodingOptionsError::FlipProbability)
+    );
+}
@@ -0,0 +1,511 @@
+//! Storing a [`FaceComparer`] on disk.
+//!
+//! A snapshot holds all stored people along with their keys, so keys stay valid across reloads.
+//! It is written to a temporary file which then replaces the previous snapshot,
+//! so a crash while saving never leaves a partially written snapshot behind.
+//!
+//! Snapshot layout (all integers and values little-endian):
+//!
+//! | bytes | content                                       |
+//! |-------|-----------------------------------------------|
+//! | 4     | magic bytes `DFRG`                            |
+//! | 1     | format version, currently `1`                 |
+//! | 8     | next key to be assigned                       |
+//! | 8     | number of people                              |
+//! | n     | the people, see below                         |
+//! | 8     | FNV-1a checksum of all preceding bytes        |
+//!
+//! A person is stored as its key (8 bytes), the length of its name (4 bytes), the name in UTF-8,
+//! the maximum outlier distance of its template (8 bytes, NaN if outliers are not rejected),
+//! the number of encodings (4 bytes), and for every encoding its fingerprint (8 bytes, `0` if
+//! unknown) followed by its 128 values as `f64`.
+//!
+//! Changes made through a [`PersistentComparer`] between two snapshots are appended to a journal
+//! next to the snapshot, with the extension `.journal`. The journal starts with the magic bytes
+//! `DFRJ` and the format version, followed by records consisting of their length (4 bytes),
+//! their kind (1 byte, `1` for storing a person and `2` for removing a key), the person or key,
+//! and the FNV-1a checksum of the kind and its content (8 bytes). A record at the end of the journal
+//! that was only partially written when the process crashed is discarded when the journal is replayed,
+//! while a damaged record followed by other records fails with [`PersistenceError::ChecksumMismatch`].
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use super::compare::FaceComparer;
+use super::encoding::FaceEncoding;
+use super::fingerprint::{FingerprintMismatch, Fnv1a, ModelFingerprint};
+use super::template::IdentityTemplate;
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"DFRG";
+const JOURNAL_MAGIC: &[u8; 4] = b"DFRJ";
+const VERSION: u8 = 1;
+
+const RECORD_PUT: u8 = 1;
+const RECORD_REMOVE: u8 = 2;
+
+impl FaceComparer {
+    /// Store all people and their keys in a snapshot file, see [`persistence`](self).
+    ///
+    /// The comparer settings, e.g. the metric, are not stored.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PersistenceError> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(SNAPSHOT_MAGIC);
+        bytes.push(VERSION);
+        write_u64(&mut bytes, self.seed() as u64);
+        write_u64(&mut bytes, self.len() as u64);
+        for (key, name, template) in self.entries() {
+            write_person(&mut bytes, key, name, template);
+        }
+
+        let checksum = checksum(&bytes);
+        write_u64(&mut bytes, checksum);
+
+        write_atomic(path.as_ref(), &bytes)
+    }
+
+    /// Load a snapshot stored with [`FaceComparer::save`], using the default settings.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PersistenceError> {
+        let mut comparer = Self::default();
+        load_snapshot(path.as_ref(), &mut comparer)?;
+        Ok(comparer)
+    }
+}
+
+/// A [`FaceComparer`] that stores every change on disk, see [`persistence`](self).
+///
+/// Changes are appended to a journal as they are made, which is merged into the snapshot by
+/// [`PersistentComparer::snapshot`].
+pub struct PersistentComparer {
+    comparer: FaceComparer,
+    path: PathBuf,
+    journal: File,
+}
+
+impl PersistentComparer {
+    /// Open the snapshot at `path` and replay its journal, creating both if they don't exist yet.
+    ///
+    /// The stored people are added to `comparer`, which provides the settings,
+    /// e.g. `FaceComparer::default().with_metric(Metric::Cosine)`.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        mut comparer: FaceComparer,
+    ) -> Result<Self, PersistenceError> {
+        let path = path.as_ref().to_path_buf();
+
+        if path.exists() {
+            load_snapshot(&path, &mut comparer)?;
+        }
+
+        let journal = open_journal(&journal_path(&path), &mut comparer)?;
+
+        Ok(Self {
+            comparer,
+            path,
+            journal,
+        })
+    }
+
+    /// See [`FaceComparer::insert`].
+    pub fn insert(&mut self, name: String, value: FaceEncoding) -> Result<(), PersistenceError> {
+        self.insert_template(name, value.into())
+    }
+
+    /// See [`FaceComparer::insert_template`].
+    pub fn insert_template(
+        &mut self,
+        name: String,
+        template: IdentityTemplate,
+    ) -> Result<(), PersistenceError> {
+        self.comparer.insert_template(name.clone(), template)?;
+        self.put(&name)
+    }
+
+    /// See [`FaceComparer::enroll`].
+    pub fn enroll(&mut self, name: String, value: FaceEncoding) -> Result<bool, PersistenceError> {
+        let accepted = self.comparer.enroll(name.clone(), value)?;
+        self.put(&name)?;
+        Ok(accepted)
+    }
+
+    /// See [`FaceComparer::remove_key`].
+    pub fn remove_key(&mut self, key: &usize) -> Result<(), PersistenceError> {
+        self.comparer.remove_key(key);
+
+        let mut content = Vec::new();
+        write_u64(&mut content, *key as u64);
+        self.append(RECORD_REMOVE, &content)
+    }
+
+    /// See [`FaceComparer::remove_name`].
+    pub fn remove_name(&mut self, name: &str) -> Result<(), PersistenceError> {
+        match self.comparer.key_of(name) {
+            Some(key) => self.remove_key(&key),
+            None => Ok(()),
+        }
+    }
+
+    /// Merge the journal into the snapshot and start a new journal.
+    pub fn snapshot(&mut self) -> Result<(), PersistenceError> {
+        self.comparer.save(&self.path)?;
+
+        // replaying the journal again after a crash at this point is harmless,
+        // as its records only repeat what is in the snapshot already
+        self.journal.set_len(0)?;
+        self.journal.seek(SeekFrom::Start(0))?;
+        self.journal.write_all(JOURNAL_MAGIC)?;
+        self.journal.write_all(&[VERSION])?;
+        self.journal.sync_data()?;
+
+        Ok(())
+    }
+
+    /// The path of the snapshot.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn into_inner(self) -> FaceComparer {
+        self.comparer
+    }
+
+    fn put(&mut self, name: &str) -> Result<(), PersistenceError> {
+        let key = self.comparer.key_of(name).unwrap();
+        let template = self.comparer.template(&key).unwrap();
+
+        let mut content = Vec::new();
+        write_person(&mut content, key, name, template);
+        self.append(RECORD_PUT, &content)
+    }
+
+    fn append(&mut self, kind: u8, content: &[u8]) -> Result<(), PersistenceError> {
+        let mut record = Vec::with_capacity(content.len() + 13);
+        record.extend_from_slice(&(content.len() as u32 + 1).to_le_bytes());
+        record.push(kind);
+        record.extend_from_slice(content);
+        let checksum = checksum(&record[4..]);
+        write_u64(&mut record, checksum);
+
+        self.journal.write_all(&record)?;
+        self.journal.sync_data()?;
+
+        Ok(())
+    }
+}
+
+impl Deref for PersistentComparer {
+    type Target = FaceComparer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.comparer
+    }
+}
+
+fn journal_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".journal");
+    path.into()
+}
+
+/// Write a file by replacing it with a completely written temporary file.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), PersistenceError> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut file = File::create(&temporary)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temporary, path)?;
+
+    // make the rename itself durable, which is not supported on every platform
+    if let Some(directory) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .and_then(|parent| File::open(parent).ok())
+    {
+        let _ = directory.sync_all();
+    }
+
+    Ok(())
+}
+
+fn load_snapshot(path: &Path, comparer: &mut FaceComparer) -> Result<(), PersistenceError> {
+    let bytes = fs::read(path)?;
+
+    if bytes.len() < 8 {
+        return Err(PersistenceError::Truncated);
+    }
+    let (content, stored) = bytes.split_at(bytes.len() - 8);
+
+    let mut reader = Reader(content);
+    check_header(&mut reader, SNAPSHOT_MAGIC, VERSION)?;
+    if u64::from_le_bytes(stored.try_into().unwrap()) != checksum(content) {
+        return Err(PersistenceError::ChecksumMismatch);
+    }
+
+    let seed = reader.u64()? as usize;
+    let len = reader.u64()?;
+    for _ in 0..len {
+        let (key, name, template) = read_person(&mut reader)?;
+        comparer.restore(key, name, template)?;
+    }
+    comparer.restore_seed(seed);
+
+    if !reader.0.is_empty() {
+        return Err(PersistenceError::Corrupted);
+    }
+
+    Ok(())
+}
+
+/// Replay the journal, discarding a partially written last record, and open it for appending.
+fn open_journal(path: &Path, comparer: &mut FaceComparer) -> Result<File, PersistenceError> {
+    let mut journal = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+
+    let mut bytes = Vec::new();
+    journal.read_to_end(&mut bytes)?;
+
+    let header_len = JOURNAL_MAGIC.len() + 1;
+    if bytes.len() < header_len {
+        // a new journal, or one whose header was only partially written
+        journal.set_len(0)?;
+        journal.seek(SeekFrom::Start(0))?;
+        journal.write_all(JOURNAL_MAGIC)?;
+        journal.write_all(&[VERSION])?;
+    } else {
+        let mut reader = Reader(&bytes);
+        check_header(&mut reader, JOURNAL_MAGIC, VERSION)?;
+        while let Some(record) = read_record(&mut reader)? {
+            replay(record, comparer)?;
+        }
+
+        journal.set_len((bytes.len() - reader.0.len()) as u64)?;
+        journal.seek(SeekFrom::End(0))?;
+    }
+    journal.sync_data()?;
+
+    Ok(journal)
+}
+
+/// The kind and content of the next complete and intact record.
+///
+/// Returns `None` at the end of the journal, or if the last record was only partially written.
+/// A damaged record followed by other records fails, as discarding it would lose later changes.
+fn read_record<'a>(reader: &mut Reader<'a>) -> Result<Option<&'a [u8]>, PersistenceError> {
+    let mut next = Reader(reader.0);
+
+    let mut read = || -> Result<_, PersistenceError> {
+        let len = next.u32()? as usize;
+        Ok((next.take(len)?, next.u64()?))
+    };
+    let (record, stored) = match read() {
+        Ok(record) => record,
+        // the record runs past the end of the journal
+        Err(_) => return Ok(None),
+    };
+
+    if stored != checksum(record) || record.is_empty() {
+        return if next.0.is_empty() {
+            Ok(None)
+        } else {
+            Err(PersistenceError::ChecksumMismatch)
+        };
+    }
+
+    *reader = next;
+    Ok(Some(record))
+}
+
+fn replay(record: &[u8], comparer: &mut FaceComparer) -> Result<(), PersistenceError> {
+    let mut reader = Reader(&record[1..]);
+
+    match record[0] {
+        RECORD_PUT => {
+            let (key, name, template) = read_person(&mut reader)?;
+            comparer.restore(key, name, template)?;
+        }
+        RECORD_REMOVE => {
+            let key = reader.u64()? as usize;
+            comparer.remove_key(&key);
+            comparer.restore_seed(key + 1);
+        }
+        _ => return Err(PersistenceError::Corrupted),
+    }
+
+    Ok(())
+}
+
+pub(crate) fn check_header(
+    reader: &mut Reader,
+    magic: &[u8; 4],
+    version: u8,
+) -> Result<(), PersistenceError> {
+    if reader.take(magic.len())? != magic {
+        return Err(PersistenceError::InvalidMagic);
+    }
+
+    let found = reader.take(1)?[0];
+    if found != version {
+        return Err(PersistenceError::UnsupportedVersion(found));
+    }
+
+    Ok(())
+}
+
+fn write_person(bytes: &mut Vec<u8>, key: usize, name: &str, template: &IdentityTemplate) {
+    write_u64(bytes, key as u64);
+    bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(name.as_bytes());
+    bytes.extend_from_slice(
+        &template
+            .max_outlier_distance()
+            .unwrap_or(f64::NAN)
+            .to_le_bytes(),
+    );
+
+    bytes.extend_from_slice(&(template.len() as u32).to_le_bytes());
+    for encoding in template.encodings() {
+        write_u64(
+            bytes,
+            encoding
+                .fingerprint()
+                .map_or(0, |fingerprint| fingerprint.as_u64()),
+        );
+        for value in encoding.as_ref() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+fn read_person(reader: &mut Reader) -> Result<(usize, String, IdentityTemplate), PersistenceError> {
+    let key = reader.u64()? as usize;
+
+    let name_len = reader.u32()? as usize;
+    let name = String::from_utf8(reader.take(name_len)?.to_vec())
+        .map_err(|_| PersistenceError::Corrupted)?;
+
+    let max_outlier_distance = f64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+
+    let len = reader.u32()?;
+    let mut encodings = Vec::new();
+    for _ in 0..len {
+        let fingerprint = ModelFingerprint::from_u64(reader.u64()?);
+        let values: Vec<f64> = reader
+            .take(128 * 8)?
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        encodings.push(
+            FaceEncoding::from_vec(&values)
+                .unwrap()
+                .with_fingerprint(fingerprint),
+        );
+    }
+
+    let mut template = IdentityTemplate::from_encodings(&encodings)?;
+    if !max_outlier_distance.is_nan() {
+        template = template.with_outlier_rejection(max_outlier_distance);
+    }
+
+    Ok((key, name, template))
+}
+
+pub(crate) fn write_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash = Fnv1a::default();
+    hash.write(bytes);
+    hash.finish()
+}
+
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], PersistenceError> {
+        if self.0.len() < len {
+            return Err(PersistenceError::Truncated);
+        }
+
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, PersistenceError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, PersistenceError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+/// A stored [`FaceComparer`] or search index could not be read or written.
+#[derive(Debug)]
+pub enum PersistenceError {
+    /// Reading or writing the file failed.
+    Io(io::Error),
+    /// The file does not start with the magic bytes of the format.
+    InvalidMagic,
+    /// The file was written by a newer version of the format.
+    UnsupportedVersion(u8),
+    /// The snapshot was modified or damaged after it was written.
+    ChecksumMismatch,
+    /// The file ends before its content is complete.
+    Truncated,
+    /// The content of the file is invalid.
+    Corrupted,
+    /// The stored encodings were generated by different models.
+    FingerprintMismatch(FingerprintMismatch),
+}
+
+impl fmt::Display for PersistenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "Failed to access the stored faces: {error}"),
+            Self::InvalidMagic => write!(f, "Not a stored face comparer or index."),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported storage format version {version}.")
+            }
+            Self::ChecksumMismatch => write!(f, "Stored faces are damaged."),
+            Self::Truncated => write!(f, "Stored faces are truncated."),
+            Self::Corrupted => write!(f, "Stored faces are invalid."),
+            Self::FingerprintMismatch(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PersistenceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::FingerprintMismatch(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PersistenceError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<FingerprintMismatch> for PersistenceError {
+    fn from(error: FingerprintMismatch) -> Self {
+        Self::FingerprintMismatch(error)
+    }
+}
@@ -0,0 +1,223 @@
+//! A compact, versioned binary format for face encodings.
+//!
+//! Layout (all integers and values little-endian):
+//!
+//! | bytes       | content                                          |
+//! |-------------|--------------------------------------------------|
+//! | 4           | magic bytes `DFRE`                               |
+//! | 1           | format version, currently `2`                    |
+//! | 1           | size of a value in bytes, `4` (f32) or `8` (f64) |
+//! | 1           | length of the model identifier                   |
+//! | n           | model identifier, UTF-8                          |
+//! | 8           | model fingerprint, `0` if unknown (since `2`)    |
+//! | 2           | number of values                                 |
+//! | 4 or 8 each | the values                                       |
+//!
+//! Encodings stored with version `1` are still accepted and have no fingerprint.
+
+use std::fmt;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+use super::fingerprint::ModelFingerprint;
+
+/// Identifies the model that produced an encoding, as stored in the binary format.
+pub const ENCODING_MODEL_ID: &str = "dlib_face_recognition_resnet_model_v1";
+
+const MAGIC: &[u8; 4] = b"DFRE";
+const VERSION: u8 = 2;
+
+/// A value of an encoding that can be stored in the binary format.
+pub(crate) trait Element: Copy {
+    const SIZE: u8;
+
+    fn write(self, bytes: &mut Vec<u8>);
+}
+
+impl Element for f32 {
+    const SIZE: u8 = 4;
+
+    fn write(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Element for f64 {
+    const SIZE: u8 = 8;
+
+    fn write(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+pub(crate) fn encode<T: Element>(values: &[T], fingerprint: Option<ModelFingerprint>) -> Vec<u8> {
+    let model = ENCODING_MODEL_ID.as_bytes();
+    let fingerprint = fingerprint.map_or(0, |fingerprint| fingerprint.as_u64());
+
+    let mut bytes = Vec::with_capacity(17 + model.len() + values.len() * T::SIZE as usize);
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.push(T::SIZE);
+    bytes.push(model.len() as u8);
+    bytes.extend_from_slice(model);
+    bytes.extend_from_slice(&fingerprint.to_le_bytes());
+    bytes.extend_from_slice(&(values.len() as u16).to_le_bytes());
+    for &value in values {
+        value.write(&mut bytes);
+    }
+
+    bytes
+}
+
+/// Decode the values and fingerprint of an encoding, widening the values to `f64` if they were stored as `f32`.
+pub(crate) fn decode(bytes: &[u8]) -> Result<(Vec<f64>, Option<ModelFingerprint>), DecodeError> {
+    let mut reader = Reader(bytes);
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(DecodeError::InvalidMagic);
+    }
+
+    let version = reader.take(1)?[0];
+    if version == 0 || version > VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+
+    let size = reader.take(1)?[0];
+    if size != f32::SIZE && size != f64::SIZE {
+        return Err(DecodeError::UnsupportedValueSize(size));
+    }
+
+    let model_len = reader.take(1)?[0] as usize;
+    let model = String::from_utf8_lossy(reader.take(model_len)?);
+    if model != ENCODING_MODEL_ID {
+        return Err(DecodeError::ModelMismatch(model.into_owned()));
+    }
+
+    let fingerprint = if version >= 2 {
+        ModelFingerprint::from_u64(u64::from_le_bytes(reader.take(8)?.try_into().unwrap()))
+    } else {
+        None
+    };
+
+    let len = u16::from_le_bytes(reader.take(2)?.try_into().unwrap()) as usize;
+    let values = reader.take(len * size as usize)?;
+    if !reader.0.is_empty() {
+        return Err(DecodeError::TrailingBytes);
+    }
+
+    let values = if size == f32::SIZE {
+        values
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()) as f64)
+            .collect()
+    } else {
+        values
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect()
+    };
+
+    Ok((values, fingerprint))
+}
+
+pub(crate) fn encode_base64(bytes: &[u8]) -> String {
+    BASE64.encode(bytes)
+}
+
+pub(crate) fn decode_base64(text: &str) -> Result<Vec<u8>, DecodeError> {
+    BASE64.decode(text).map_err(|_| DecodeError::InvalidBase64)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.0.len() < len {
+            return Err(DecodeError::Truncated);
+        }
+
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+}
+
+/// A stored face encoding could not be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The data does not start with the magic bytes of the format.
+    InvalidMagic,
+    /// The data was written by a newer version of the format.
+    UnsupportedVersion(u8),
+    /// The values are neither `f32` nor `f64`.
+    UnsupportedValueSize(u8),
+    /// The encoding was produced by a different model, so it can't be compared with ours.
+    ModelMismatch(String),
+    /// The data ends before the encoding is complete.
+    Truncated,
+    /// There is more data after the encoding.
+    TrailingBytes,
+    /// The encoding does not have the number of values of a face encoding.
+    InvalidLength(usize),
+    /// The text is not valid base64.
+    InvalidBase64,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidMagic => write!(f, "Not a face encoding."),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported face encoding format version {version}.")
+            }
+            Self::UnsupportedValueSize(size) => {
+                write!(f, "Unsupported face encoding value size of {size} bytes.")
+            }
+            Self::ModelMismatch(model) => write!(
+                f,
+                "Face encoding was produced by '{model}', expected '{ENCODING_MODEL_ID}'."
+            ),
+            Self::Truncated => write!(f, "Face encoding is truncated."),
+            Self::TrailingBytes => write!(f, "Unexpected data after the face encoding."),
+            Self::InvalidLength(len) => {
+                write!(f, "Face encoding has {len} values, expected 128.")
+            }
+            Self::InvalidBase64 => write!(f, "Face encoding is not valid base64."),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[test]
+fn serialization_test() {
+    let values = [0.25_f32, -1.0, 3.5];
+    let fingerprint = ModelFingerprint::from_u64(0x1234);
+
+    let bytes = encode(&values, fingerprint);
+    assert_eq!(&bytes[..6], b"DFRE\x02\x04");
+    assert_eq!(decode(&bytes), Ok((vec![0.25, -1.0, 3.5], fingerprint)));
+    assert_eq!(
+        decode(&bytes[..bytes.len() - 1]),
+        Err(DecodeError::Truncated)
+    );
+
+    let text = encode_base64(&encode(&[1.0_f64, 2.0], None));
+    assert_eq!(
+        decode(&decode_base64(&text).unwrap()),
+        Ok((vec![1.0, 2.0], None))
+    );
+    assert_eq!(decode_base64("!"), Err(DecodeError::InvalidBase64));
+
+    let mut bytes = encode(&values, None);
+    bytes[4] = 3;
+    assert_eq!(decode(&bytes), Err(DecodeError::UnsupportedVersion(3)));
+
+    // version 1 has no fingerprint
+    let model = 7 + ENCODING_MODEL_ID.len();
+    let mut bytes = encode(&values, None);
+    bytes[4] = 1;
+    bytes.drain(model..model + 8);
+    assert_eq!(decode(&bytes), Ok((vec![0.25, -1.0, 3.5], None)));
+}
@@ -0,0 +1,172 @@
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+
+use super::encoding::FaceEncoding;
+use super::fingerprint::FingerprintMismatch;
+use super::gallery::Gallery;
+use super::template::IdentityTemplate;
+
+/// A change to a [`SharedGallery`], see [`SharedGallery::subscribe`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GalleryEvent<K> {
+    /// An identity was stored under the key, possibly replacing another one.
+    Inserted(K),
+    /// An encoding was added to the template stored under the key.
+    Enrolled(K),
+    /// The identity stored under the key was removed.
+    Removed(K),
+}
+
+/// A [`Gallery`] shared between threads, which identify faces while others enroll people.
+///
+/// Readers work on immutable snapshots, so they never wait for a writer and a batch of
+/// queries always sees the same identities. Writers copy the gallery, apply their change
+/// and publish the copy as the new snapshot, which makes writes more expensive than reads.
+pub struct SharedGallery<K, M = ()> {
+    current: RwLock<Arc<Gallery<K, M>>>,
+    /// Serializes writers, which also notify the subscribers.
+    subscribers: Mutex<Vec<Sender<GalleryEvent<K>>>>,
+}
+
+impl<K, M> Default for SharedGallery<K, M> {
+    fn default() -> Self {
+        Gallery::default().into()
+    }
+}
+
+impl<K, M> From<Gallery<K, M>> for SharedGallery<K, M> {
+    fn from(gallery: Gallery<K, M>) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(gallery)),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, M: Clone> SharedGallery<K, M> {
+    pub fn new(gallery: Gallery<K, M>) -> Self {
+        gallery.into()
+    }
+
+    /// The current state of the gallery, which is not affected by later changes.
+    pub fn snapshot(&self) -> Arc<Gallery<K, M>> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Receive an event for every change from now on.
+    ///
+    /// Events are sent after the change was published, so a snapshot taken when receiving
+    /// an event includes the change. Dropping the receiver unsubscribes.
+    pub fn subscribe(&self) -> Receiver<GalleryEvent<K>> {
+        let (sender, receiver) = mpsc::channel();
+        self.writer().push(sender);
+        receiver
+    }
+
+    /// Like [`Gallery::find`] on the current snapshot.
+    ///
+    /// Faces encoded by a different model than the stored encodings match nobody, see [`SharedGallery::try_find`].
+    pub fn find(&self, face: &FaceEncoding) -> Option<(K, M)> {
+        self.try_find(face).ok().flatten()
+    }
+
+    /// Like [`SharedGallery::find`], but fails if the face was encoded by a different model than the stored encodings.
+    pub fn try_find(&self, face: &FaceEncoding) -> Result<Option<(K, M)>, FingerprintMismatch> {
+        Ok(self
+            .snapshot()
+            .try_find(face)?
+            .map(|(key, metadata)| (key.clone(), metadata.clone())))
+    }
+
+    /// Like [`Gallery::insert`], publishing a new snapshot.
+    pub fn insert(
+        &self,
+        key: K,
+        encoding: FaceEncoding,
+        metadata: M,
+    ) -> Result<Option<M>, FingerprintMismatch> {
+        self.insert_template(key, encoding.into(), metadata)
+    }
+
+    /// Like [`Gallery::insert_template`], publishing a new snapshot.
+    pub fn insert_template(
+        &self,
+        key: K,
+        template: IdentityTemplate,
+        metadata: M,
+    ) -> Result<Option<M>, FingerprintMismatch> {
+        let event = GalleryEvent::Inserted(key.clone());
+
+        self.update(|gallery| {
+            let previous = gallery.insert_template(key, template, metadata)?;
+            Ok((previous, Some(event)))
+        })
+    }
+
+    /// Like [`Gallery::enroll`], publishing a new snapshot.
+    pub fn enroll(
+        &self,
+        key: &K,
+        encoding: FaceEncoding,
+    ) -> Result<Option<bool>, FingerprintMismatch> {
+        self.update(|gallery| {
+            let accepted = gallery.enroll(key, encoding)?;
+            let event = (accepted == Some(true)).then(|| GalleryEvent::Enrolled(key.clone()));
+            Ok((accepted, event))
+        })
+    }
+
+    /// Like [`Gallery::remove`], publishing a new snapshot.
+    pub fn remove(&self, key: &K) -> Option<M> {
+        if !self.snapshot().contains_key(key) {
+            return None;
+        }
+
+        self.update(|gallery| {
+            let metadata = gallery.remove(key);
+            let event = metadata
+                .is_some()
+                .then(|| GalleryEvent::Removed(key.clone()));
+            Ok::<_, Infallible>((metadata, event))
+        })
+        .unwrap()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshot().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshot().is_empty()
+    }
+
+    /// Apply a change to a copy of the gallery and publish it, unless the change failed
+    /// or did not produce an event.
+    fn update<T, E>(
+        &self,
+        change: impl FnOnce(&mut Gallery<K, M>) -> Result<(T, Option<GalleryEvent<K>>), E>,
+    ) -> Result<T, E> {
+        let mut subscribers = self.writer();
+
+        let mut gallery = Gallery::clone(&self.snapshot());
+        let (value, event) = change(&mut gallery)?;
+
+        if let Some(event) = event {
+            *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(gallery);
+            subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+
+        Ok(value)
+    }
+
+    fn writer(&self) -> MutexGuard<'_, Vec<Sender<GalleryEvent<K>>>> {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,275 @@
+//! Distance kernels using the vector instructions of the CPU, with a portable fallback.
+//!
+//! The instructions are detected at runtime, so the same binary runs on every CPU.
+
+/// The squared euclidean distance between two slices of equal length.
+///
+/// # Panics
+///
+/// Panics if the lengths differ, as the vector kernels read both slices up to the length of the first one.
+pub(crate) fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    assert_eq!(a.len(), b.len());
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") && is_x86_feature_detected!("fma") {
+            return unsafe { x86::squared_distance(a, b) };
+        }
+    }
+
+    portable::squared_distance(a, b)
+}
+
+/// The squared euclidean distance between a query and values quantized to `value * scale`.
+///
+/// Panics if the lengths differ, see [`squared_distance`].
+pub(crate) fn squared_distance_i8(query: &[f32], values: &[i8], scale: f32) -> f32 {
+    assert_eq!(query.len(), values.len());
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            return unsafe { x86::squared_distance_i8(query, values, scale) };
+        }
+    }
+
+    portable::squared_distance_i8(query, values, scale)
+}
+
+/// The squared euclidean distance between a query and values stored as half precision floats.
+///
+/// Panics if the lengths differ, see [`squared_distance`].
+pub(crate) fn squared_distance_f16(query: &[f32], values: &[u16]) -> f32 {
+    assert_eq!(query.len(), values.len());
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx")
+            && is_x86_feature_detected!("fma")
+            && is_x86_feature_detected!("f16c")
+        {
+            return unsafe { x86::squared_distance_f16(query, values) };
+        }
+    }
+
+    portable::squared_distance_f16(query, values)
+}
+
+/// Convert to the bits of a half precision float, rounding to the nearest value.
+pub(crate) fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // infinity and nan
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // round half to even, where `rest` holds the `shift` bits dropped from `half`
+    let round = |half: u32, rest: u32, shift: u32| {
+        let halfway = 1 << (shift - 1);
+        half + (rest > halfway || (rest == halfway && half & 1 == 1)) as u32
+    };
+
+    // subnormal values, written as `half * 2^-24`
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        return sign | round(mantissa >> shift, mantissa & ((1 << shift) - 1), shift) as u16;
+    }
+
+    // a carry into the exponent is correct, up to overflowing into infinity
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    sign | round(half, mantissa & 0x1fff, 13) as u16
+}
+
+/// Convert from the bits of a half precision float.
+pub(crate) fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            let value = mantissa as f32 / (1 << 24) as f32;
+            return if sign == 0 { value } else { -value };
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+
+    f32::from_bits(bits)
+}
+
+mod portable {
+    use super::f16_to_f32;
+
+    /// Several independent sums, which the compiler turns into vector instructions.
+    const LANES: usize = 8;
+
+    fn sum_lanes(a: &[f32], distance: impl Fn(usize) -> f32) -> f32 {
+        let mut sums = [0.0; LANES];
+        let chunks = a.len() / LANES;
+
+        for chunk in 0..chunks {
+            for (lane, sum) in sums.iter_mut().enumerate() {
+                *sum += distance(chunk * LANES + lane);
+            }
+        }
+        for index in chunks * LANES..a.len() {
+            sums[0] += distance(index);
+        }
+
+        sums.iter().sum()
+    }
+
+    pub(super) fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+        sum_lanes(a, |index| {
+            let difference = a[index] - b[index];
+            difference * difference
+        })
+    }
+
+    pub(super) fn squared_distance_i8(query: &[f32], values: &[i8], scale: f32) -> f32 {
+        sum_lanes(query, |index| {
+            let difference = query[index] - values[index] as f32 * scale;
+            difference * difference
+        })
+    }
+
+    pub(super) fn squared_distance_f16(query: &[f32], values: &[u16]) -> f32 {
+        sum_lanes(query, |index| {
+            let difference = query[index] - f16_to_f32(values[index]);
+            difference * difference
+        })
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::portable;
+
+    /// The sum of all eight lanes.
+    #[target_feature(enable = "avx")]
+    fn sum(value: __m256) -> f32 {
+        let value = _mm_add_ps(
+            _mm256_castps256_ps128(value),
+            _mm256_extractf128_ps(value, 1),
+        );
+        let value = _mm_add_ps(value, _mm_movehl_ps(value, value));
+        let value = _mm_add_ss(value, _mm_shuffle_ps(value, value, 1));
+        _mm_cvtss_f32(value)
+    }
+
+    #[target_feature(enable = "avx,fma")]
+    pub(super) unsafe fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+        let chunks = a.len() / 8;
+
+        let total = unsafe {
+            let mut sums = _mm256_setzero_ps();
+            for chunk in 0..chunks {
+                let difference = _mm256_sub_ps(
+                    _mm256_loadu_ps(a.as_ptr().add(chunk * 8)),
+                    _mm256_loadu_ps(b.as_ptr().add(chunk * 8)),
+                );
+                sums = _mm256_fmadd_ps(difference, difference, sums);
+            }
+            sum(sums)
+        };
+
+        total + portable::squared_distance(&a[chunks * 8..], &b[chunks * 8..])
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub(super) unsafe fn squared_distance_i8(query: &[f32], values: &[i8], scale: f32) -> f32 {
+        let chunks = query.len() / 8;
+
+        let total = unsafe {
+            let scale = _mm256_set1_ps(scale);
+            let mut sums = _mm256_setzero_ps();
+            for chunk in 0..chunks {
+                let packed = _mm_loadl_epi64(values.as_ptr().add(chunk * 8) as *const __m128i);
+                let values = _mm256_mul_ps(_mm256_cvtepi32_ps(_mm256_cvtepi8_epi32(packed)), scale);
+                let difference =
+                    _mm256_sub_ps(_mm256_loadu_ps(query.as_ptr().add(chunk * 8)), values);
+                sums = _mm256_fmadd_ps(difference, difference, sums);
+            }
+            sum(sums)
+        };
+
+        total + portable::squared_distance_i8(&query[chunks * 8..], &values[chunks * 8..], scale)
+    }
+
+    #[target_feature(enable = "avx,fma,f16c")]
+    pub(super) unsafe fn squared_distance_f16(query: &[f32], values: &[u16]) -> f32 {
+        let chunks = query.len() / 8;
+
+        let total = unsafe {
+            let mut sums = _mm256_setzero_ps();
+            for chunk in 0..chunks {
+                let packed = _mm_loadu_si128(values.as_ptr().add(chunk * 8) as *const __m128i);
+                let difference = _mm256_sub_ps(
+                    _mm256_loadu_ps(query.as_ptr().add(chunk * 8)),
+                    _mm256_cvtph_ps(packed),
+                );
+                sums = _mm256_fmadd_ps(difference, difference, sums);
+            }
+            sum(sums)
+        };
+
+        total + portable::squared_distance_f16(&query[chunks * 8..], &values[chunks * 8..])
+    }
+}
+
+#[test]
+fn simd_test() {
+    let a: Vec<f32> = (0..131).map(|i| (i as f32 * 0.37).sin() * 0.3).collect();
+    let b: Vec<f32> = (0..131).map(|i| (i as f32 * 0.11).cos() * 0.3).collect();
+
+    let expected: f32 = a.iter().zip(&b).map(|(a, b)| (a - b) * (a - b)).sum();
+    assert!((squared_distance(&a, &b) - expected).abs() < 1e-4);
+    assert!((portable::squared_distance(&a, &b) - expected).abs() < 1e-4);
+
+    let halves: Vec<u16> = b.iter().map(|&value| f32_to_f16(value)).collect();
+    assert!((squared_distance_f16(&a, &halves) - expected).abs() < 1e-3);
+    assert!((portable::squared_distance_f16(&a, &halves) - expected).abs() < 1e-3);
+
+    let scale = 0.3 / 127.0;
+    let bytes: Vec<i8> = b
+        .iter()
+        .map(|&value| (value / scale).round() as i8)
+        .collect();
+    assert!((squared_distance_i8(&a, &bytes, scale) - expected).abs() < 1e-2);
+    assert!((portable::squared_distance_i8(&a, &bytes, scale) - expected).abs() < 1e-2);
+
+    // shorter values would be read out of bounds by the vector kernels
+    assert!(std::panic::catch_unwind(|| squared_distance(&a, &b[..64])).is_err());
+    assert!(std::panic::catch_unwind(|| squared_distance_f16(&a, &halves[..64])).is_err());
+    assert!(std::panic::catch_unwind(|| squared_distance_i8(&a, &bytes[..64], scale)).is_err());
+
+    for value in [0.0, -0.0, 1.0, -2.5, 0.1, 65504.0, 1e-5, 6e-8, 1e-9] {
+        let half = f16_to_f32(f32_to_f16(value));
+        assert!(
+            (half - value).abs() <= value.abs() * 1e-3 + 6e-8,
+            "{value} became {half}"
+        );
+    }
+    assert_eq!(f32_to_f16(1.0), 0x3c00);
+    assert_eq!(f32_to_f16(65520.0), 0x7c00);
+    assert_eq!(f16_to_f32(f32_to_f16(f32::NEG_INFINITY)), f32::NEG_INFINITY);
+    assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+}
@@ -0,0 +1,254 @@
+use super::encoding::FaceEncoding;
+use super::fingerprint::{FingerprintMismatch, ModelFingerprint};
+use super::metric::Metric;
+
+/// How a face is compared against the encodings of an [`IdentityTemplate`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Scoring {
+    /// The distance to the closest encoding of the template.
+    #[default]
+    MinDistance,
+    /// The mean distance to all encodings of the template.
+    MeanDistance,
+    /// The distance to the mean of all encodings of the template.
+    Centroid,
+}
+
+/// All encodings enrolled for a single person, e.g. from several photos.
+///
+/// Encodings can be added incrementally. With [`IdentityTemplate::with_outlier_rejection`],
+/// encodings that lie too far from the rest of the template, e.g. because the wrong face was
+/// detected in an enrollment photo, are rejected instead of being added.
+#[derive(Clone, Debug, Default)]
+pub struct IdentityTemplate {
+    encodings: Vec<FaceEncoding>,
+    centroid: Option<FaceEncoding>,
+    max_outlier_distance: Option<f64>,
+}
+
+impl IdentityTemplate {
+    /// Create an empty template.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject encodings whose euclidean distance to the centroid of the template exceeds `max_distance`.
+    ///
+    /// Encodings already in the template are kept, so only encodings added from now on are checked.
+    pub fn with_outlier_rejection(mut self, max_distance: f64) -> Self {
+        self.max_outlier_distance = Some(max_distance);
+        self
+    }
+
+    /// The maximum distance to the centroid of the template, if outliers are rejected.
+    pub fn max_outlier_distance(&self) -> Option<f64> {
+        self.max_outlier_distance
+    }
+
+    /// Create a template from the encodings of several enrollment photos.
+    pub fn from_encodings(encodings: &[FaceEncoding]) -> Result<Self, FingerprintMismatch> {
+        let mut template = Self::new();
+        template.enroll(encodings)?;
+        Ok(template)
+    }
+
+    /// Add an encoding to the template.
+    ///
+    /// Returns `false` if the encoding was rejected as an outlier.
+    pub fn add(&mut self, encoding: FaceEncoding) -> Result<bool, FingerprintMismatch> {
+        let rejected = self.enroll(std::slice::from_ref(&encoding))?;
+
+        Ok(rejected.is_empty())
+    }
+
+    /// Add the encodings of several enrollment photos to the template.
+    ///
+    /// The encodings are added together, so a single outlier among them is rejected even if the
+    /// template was empty before. Outliers are removed one at a time, starting with the encoding
+    /// furthest from the centroid, until all remaining encodings are close enough.
+    ///
+    /// Returns the indices of the rejected encodings.
+    pub fn enroll(
+        &mut self,
+        encodings: &[FaceEncoding],
+    ) -> Result<Vec<usize>, FingerprintMismatch> {
+        let mut fingerprint = self.fingerprint();
+        for encoding in encodings {
+            FingerprintMismatch::check(fingerprint, encoding.fingerprint())?;
+            fingerprint = fingerprint.or(encoding.fingerprint());
+        }
+
+        let mut accepted: Vec<usize> = (0..encodings.len()).collect();
+        let mut rejected = Vec::new();
+
+        if let Some(max_distance) = self.max_outlier_distance {
+            while !accepted.is_empty() {
+                let centroid = mean(
+                    self.encodings
+                        .iter()
+                        .chain(accepted.iter().map(|&index| &encodings[index])),
+                );
+
+                let (position, distance) = accepted
+                    .iter()
+                    .map(|&index| Metric::Euclidean.distance(encodings[index].as_ref(), &centroid))
+                    .enumerate()
+                    .max_by(|(_, x), (_, y)| x.total_cmp(y))
+                    .unwrap();
+
+                if distance <= max_distance {
+                    break;
+                }
+
+                rejected.push(accepted.remove(position));
+            }
+        }
+
+        self.encodings
+            .extend(accepted.into_iter().map(|index| encodings[index].clone()));
+        self.update_centroid();
+
+        rejected.sort_unstable();
+        Ok(rejected)
+    }
+
+    /// Remove the encoding at `index`, returning it if it exists.
+    pub fn remove(&mut self, index: usize) -> Option<FaceEncoding> {
+        if index >= self.encodings.len() {
+            return None;
+        }
+
+        let encoding = self.encodings.remove(index);
+        self.update_centroid();
+        Some(encoding)
+    }
+
+    /// The enrolled encodings.
+    pub fn encodings(&self) -> &[FaceEncoding] {
+        &self.encodings
+    }
+
+    /// The mean of the enrolled encodings, or `None` if the template is empty.
+    pub fn centroid(&self) -> Option<&FaceEncoding> {
+        self.centroid.as_ref()
+    }
+
+    /// The fingerprint of the model that generated the enrolled encodings, if known.
+    pub fn fingerprint(&self) -> Option<ModelFingerprint> {
+        self.encodings.iter().find_map(FaceEncoding::fingerprint)
+    }
+
+    /// The distance between a face and the template, or `None` if the template is empty.
+    ///
+    /// The fingerprints are ignored, compare them with [`IdentityTemplate::fingerprint`] first.
+    pub fn distance(&self, face: &FaceEncoding, metric: Metric, scoring: Scoring) -> Option<f64> {
+        let centroid = self.centroid.as_ref()?;
+
+        let distance = match scoring {
+            Scoring::MinDistance => self
+                .encodings
+                .iter()
+                .map(|encoding| encoding.distance_with(face, metric))
+                .min_by(|x, y| x.total_cmp(y))
+                .unwrap(),
+            Scoring::MeanDistance => {
+                self.encodings
+                    .iter()
+                    .map(|encoding| encoding.distance_with(face, metric))
+                    .sum::<f64>()
+                    / self.encodings.len() as f64
+            }
+            Scoring::Centroid => centroid.distance_with(face, metric),
+        };
+
+        Some(distance)
+    }
+
+    pub fn len(&self) -> usize {
+        self.encodings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.encodings.is_empty()
+    }
+
+    fn update_centroid(&mut self) {
+        self.centroid = if self.encodings.is_empty() {
+            None
+        } else {
+            let values = mean(self.encodings.iter());
+
+            Some(
+                FaceEncoding::from_vec(&values)
+                    .unwrap()
+                    .with_fingerprint(self.fingerprint()),
+            )
+        };
+    }
+}
+
+impl From<FaceEncoding> for IdentityTemplate {
+    fn from(encoding: FaceEncoding) -> Self {
+        let mut template = Self::new();
+        template.encodings.push(encoding);
+        template.update_centroid();
+        template
+    }
+}
+
+/// The element-wise mean of a non-empty collection of encodings.
+fn mean<'a>(encodings: impl Iterator<Item = &'a FaceEncoding>) -> Vec<f64> {
+    let mut sum = vec![0.0; 128];
+    let mut count = 0;
+
+    for encoding in encodings {
+        for (sum, value) in sum.iter_mut().zip(encoding.as_ref()) {
+            *sum += value;
+        }
+        count += 1;
+    }
+
+    sum.iter().map(|sum| sum / count as f64).collect()
+}
+
+#[test]
+fn template_test() {
+    let encodings: Vec<_> = [0.0, 0.02, 0.04]
+        .iter()
+        .map(|&scalar| FaceEncoding::new_from_scalar(scalar))
+        .collect();
+    let outlier = FaceEncoding::new_from_scalar(1.0);
+
+    let mut template = IdentityTemplate::new().with_outlier_rejection(0.6);
+    assert_eq!(template.enroll(&encodings), Ok(vec![]));
+    assert_eq!(
+        template.centroid(),
+        Some(&FaceEncoding::new_from_scalar(0.02))
+    );
+    assert_eq!(template.add(outlier.clone()), Ok(false));
+    assert_eq!(template.len(), 3);
+
+    let face = FaceEncoding::new_from_scalar(0.04);
+    let distance = |scoring| {
+        template
+            .distance(&face, Metric::Euclidean, scoring)
+            .unwrap()
+    };
+    assert_eq!(distance(Scoring::MinDistance), 0.0);
+    assert!((distance(Scoring::Centroid) - 0.02 * 128.0_f64.sqrt()).abs() < 1e-9);
+    assert!((distance(Scoring::MeanDistance) - 0.02 * 128.0_f64.sqrt()).abs() < 1e-9);
+
+    // the outlier is rejected even when enrolled together with the other encodings
+    let mut all = encodings.clone();
+    all.insert(1, outlier);
+    let mut template = IdentityTemplate::new().with_outlier_rejection(0.6);
+    assert_eq!(template.enroll(&all), Ok(vec![1]));
+
+    assert_eq!(template.remove(0), Some(FaceEncoding::new_from_scalar(0.0)));
+    assert_eq!(template.len(), 2);
+    assert_eq!(
+        IdentityTemplate::new().distance(&face, Metric::Euclidean, Scoring::Centroid),
+        None
+    );
+}
@@ -1,4 +1,5 @@
 use super::landmarks::FaceLandmarks;
+use crate::face_detection::FaceLocations;
 use crate::geometry::Rectangle;
 use crate::matrix::ImageMatrix;
 
@@ -7,4 +8,18 @@ pub trait LandmarkPredictorTrait {
     ///
     /// This will generally always return the number of landmarks as defined by the model.
     fn face_landmarks(&self, image: &ImageMatrix, rect: &Rectangle) -> FaceLandmarks;
+
+    /// Detect face landmarks for every face location, in the same order as the locations.
+    ///
+    /// The result can be passed to the face encoder as is.
+    fn face_landmarks_batch(
+        &self,
+        image: &ImageMatrix,
+        locations: &FaceLocations,
+    ) -> Vec<FaceLandmarks> {
+        locations
+            .iter()
+            .map(|rect| self.face_landmarks(image, rect))
+            .collect()
+    }
 }
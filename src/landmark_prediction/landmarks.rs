@@ -1,7 +1,8 @@
+use std::fmt;
 use std::ops::Deref;
 use std::slice;
 
-use crate::geometry::Point;
+use crate::geometry::{Point, Rectangle};
 
 cpp_class!(
     /// A wrapper around the dlib `full_object_detection` class, which internally has a `std::vector<point>`.
@@ -9,6 +10,47 @@ cpp_class!(
     pub unsafe struct FaceLandmarks as "dlib::full_object_detection"
 );
 
+impl FaceLandmarks {
+    /// The part counts of the landmark models that faces can be aligned with,
+    /// which are the 5 and 68 point models provided by dlib.
+    pub const SUPPORTED_PART_COUNTS: [usize; 2] = [5, 68];
+
+    /// Create landmarks from externally produced points, e.g. another model or a stored annotation.
+    ///
+    /// The points must follow the layout of either the 5 or the 68 point dlib model,
+    /// so that they can be used to align and encode the face.
+    pub fn new(rect: Rectangle, parts: &[Point]) -> Result<Self, PartCountError> {
+        if !Self::SUPPORTED_PART_COUNTS.contains(&parts.len()) {
+            return Err(PartCountError(parts.len()));
+        }
+
+        let rect = &rect;
+        let num_parts = parts.len();
+        let parts = parts.as_ptr();
+
+        Ok(unsafe {
+            cpp!([rect as "const dlib::rectangle*", parts as "const dlib::point*", num_parts as "size_t"] -> FaceLandmarks as "dlib::full_object_detection" {
+                std::vector<dlib::point> points(parts, parts + num_parts);
+                return dlib::full_object_detection(*rect, points);
+            })
+        })
+    }
+
+    /// The face rectangle the landmarks were predicted from.
+    pub fn rect(&self) -> Rectangle {
+        unsafe {
+            cpp!([self as "const dlib::full_object_detection*"] -> Rectangle as "dlib::rectangle" {
+                return self->get_rect();
+            })
+        }
+    }
+
+    /// Whether the landmarks can be used to align the face, see [`FaceLandmarks::SUPPORTED_PART_COUNTS`].
+    pub fn is_alignable(&self) -> bool {
+        Self::SUPPORTED_PART_COUNTS.contains(&self.len())
+    }
+}
+
 impl Deref for FaceLandmarks {
     type Target = [Point];
 
@@ -34,6 +76,22 @@ impl Deref for FaceLandmarks {
     }
 }
 
+/// The number of parts given to [`FaceLandmarks::new`] is not supported.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PartCountError(pub usize);
+
+impl fmt::Display for PartCountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid number of landmark parts: expected 5 or 68, got {}.",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for PartCountError {}
+
 #[test]
 fn test_default_landmarks() {
     // ensure that FaceLandmarks::default() doesnt allow memory violations in safe code
@@ -43,3 +101,24 @@ fn test_default_landmarks() {
     assert_eq!(landmarks.len(), 0);
     assert_eq!(landmarks.get(0), None);
 }
+
+#[test]
+fn test_new_landmarks() {
+    let rect = Rectangle {
+        left: 10,
+        top: 20,
+        right: 110,
+        bottom: 120,
+    };
+    let parts: Vec<_> = (0..5).map(|i| Point::new(10 + i * 20, 70)).collect();
+
+    let landmarks = FaceLandmarks::new(rect, &parts).unwrap();
+    assert_eq!(landmarks.rect(), rect);
+    assert_eq!(&*landmarks, parts.as_slice());
+    assert!(landmarks.is_alignable());
+
+    assert_eq!(
+        FaceLandmarks::new(rect, &parts[..4]).err(),
+        Some(PartCountError(4))
+    );
+}
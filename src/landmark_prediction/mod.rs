@@ -5,5 +5,5 @@ mod landmarks;
 mod model;
 
 pub use self::base::LandmarkPredictorTrait;
-pub use self::landmarks::FaceLandmarks;
+pub use self::landmarks::{FaceLandmarks, PartCountError};
 pub use self::model::LandmarkPredictor;
@@ -1,8 +1,11 @@
+use std::ops::Deref;
 use std::path::Path;
+use std::slice;
 
 use super::base::LandmarkPredictorTrait;
 use super::landmarks::FaceLandmarks;
 use crate::base::path_as_cstring;
+use crate::face_detection::FaceLocations;
 use crate::geometry::Rectangle;
 use crate::matrix::ImageMatrix;
 
@@ -56,6 +59,93 @@ impl LandmarkPredictor {
             Ok(Self { inner })
         }
     }
+
+    /// Detect face landmarks for every face location, spreading the faces over `num_threads` threads.
+    ///
+    /// The predictor is only read during inference, so this is safe to do from a single predictor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if dlib fails to predict the landmarks of a face, e.g. because it runs out of memory.
+    pub fn face_landmarks_batch_parallel(
+        &self,
+        image: &ImageMatrix,
+        locations: &FaceLocations,
+        num_threads: usize,
+    ) -> Vec<FaceLandmarks> {
+        let predictor = &self.inner;
+        let num_faces = locations.len();
+        let rects = locations.as_ptr();
+
+        let mut landmarks = FaceLandmarksVec::default();
+
+        let predicted = unsafe {
+            let out = &mut landmarks;
+
+            cpp!([
+                    predictor as "const dlib::shape_predictor*",
+                    image as "const dlib::matrix<dlib::rgb_pixel>*",
+                    rects as "const dlib::rectangle*",
+                    num_faces as "size_t",
+                    num_threads as "size_t",
+                    out as "std::vector<dlib::full_object_detection>*"
+                ] -> bool as "bool" {
+                try {
+                    out->resize(num_faces);
+
+                    // exceptions must not escape the worker threads, so failures are only recorded
+                    std::atomic<bool> failed(false);
+                    auto predict = [&](long offset) {
+                        try {
+                            (*out)[offset] = (*predictor)(*image, rects[offset]);
+                        } catch (...) {
+                            failed = true;
+                        }
+                    };
+
+                    if (num_threads <= 1) {
+                        for (size_t offset = 0; offset < num_faces; offset++) {
+                            predict(offset);
+                        }
+                    } else {
+                        dlib::parallel_for(num_threads, 0, num_faces, predict);
+                    }
+                    return !failed;
+                } catch (...) {
+                    return false;
+                }
+            })
+        };
+
+        assert!(predicted, "Failed to predict the face landmarks.");
+        landmarks.to_vec()
+    }
+}
+
+cpp_class!(unsafe struct FaceLandmarksVec as "std::vector<dlib::full_object_detection>");
+
+impl Deref for FaceLandmarksVec {
+    type Target = [FaceLandmarks];
+
+    fn deref(&self) -> &Self::Target {
+        let len = unsafe {
+            cpp!([self as "std::vector<dlib::full_object_detection>*"] -> usize as "size_t" {
+                return self->size();
+            })
+        };
+
+        if len == 0 {
+            &[]
+        } else {
+            unsafe {
+                let pointer = cpp!([self as "std::vector<dlib::full_object_detection>*"] -> *const FaceLandmarks as "dlib::full_object_detection*" {
+                    return &(*self)[0];
+                });
+
+                slice::from_raw_parts(pointer, len)
+            }
+        }
+    }
 }
 
 impl LandmarkPredictorTrait for LandmarkPredictor {
@@ -68,4 +158,12 @@ impl LandmarkPredictorTrait for LandmarkPredictor {
             })
         }
     }
+
+    fn face_landmarks_batch(
+        &self,
+        image: &ImageMatrix,
+        locations: &FaceLocations,
+    ) -> Vec<FaceLandmarks> {
+        self.face_landmarks_batch_parallel(image, locations, 1)
+    }
 }
@@ -24,6 +24,9 @@ mod wrapper;
 mod base;
 #[cfg(feature = "embed-any")]
 mod embed;
+mod face_alignment;
+mod face_classification;
+mod face_clustering;
 mod face_detection;
 mod face_encoding;
 mod geometry;
@@ -33,8 +36,23 @@ mod matrix;
 pub use self::geometry::{Point, Rectangle};
 pub use self::matrix::ImageMatrix;
 
+pub use self::face_alignment::{AffineTransform, ChipDetails, FaceChip, FaceChipError};
+pub use self::face_classification::{SvmClassifier, SvmTrainer, TrainError};
+pub use self::face_clustering::{
+    Agglomerative, ChineseWhispers, ClusterQuality, Dbscan, Deduplicator, Dendrogram,
+    DuplicateGroup, Merge,
+};
 pub use self::face_detection::{FaceDetector, FaceDetectorCnn, FaceDetectorTrait, FaceLocations};
 pub use self::face_encoding::{
-    FaceComparer, FaceEncoderNetwork, FaceEncoderTrait, FaceEncoding, FaceEncodings,
+    ArraySizeError, Averaging, BruteForceIndex, Calibration, CalibrationError, ChipSizeError,
+    DecodeError, ENCODING_MODEL_ID, EncodingOptions, EncodingOptionsError, ErrorRates,
+    FaceComparer, FaceEncoderNetwork, FaceEncoderTrait, FaceEncoding, FaceEncodingF32,
+    FaceEncodings, FaceMatch, FingerprintMismatch, FlatIndex, Gallery, GalleryEvent, GalleryMatch,
+    HnswIndex, HnswParams, IdentityTemplate, JitterOptions, Metric, ModelFingerprint,
+    ParseFingerprintError, PersistenceError, PersistentComparer, Quantization, Scoring,
+    SearchIndex, SharedGallery, measure_recall,
+};
+pub use self::face_encoding::{persistence, serialization};
+pub use self::landmark_prediction::{
+    FaceLandmarks, LandmarkPredictor, LandmarkPredictorTrait, PartCountError,
 };
-pub use self::landmark_prediction::{FaceLandmarks, LandmarkPredictor, LandmarkPredictorTrait};
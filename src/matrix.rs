@@ -1,6 +1,6 @@
 use std::ops::Deref;
 
-use image::{ImageBuffer, Rgb};
+use image::{ImageBuffer, Rgb, RgbImage};
 
 cpp_class!(
     /// A wrapper around a `matrix<rgb_pixel>`, dlibs own image class.
@@ -42,9 +42,55 @@ impl ImageMatrix {
 
         unsafe { Self::new(width, height, ptr) }
     }
+
+    /// Copy the matrix back into an rgb image.
+    pub fn to_image(&self) -> RgbImage {
+        let width = self.width();
+        let height = self.height();
+
+        let mut buffer = vec![0u8; width * height * 3];
+        let ptr = buffer.as_mut_ptr();
+
+        unsafe {
+            cpp!([self as "const dlib::matrix<dlib::rgb_pixel>*", ptr as "uint8_t*"] {
+                size_t offset = 0;
+
+                for (long y = 0; y < self->nr(); y++) {
+                    for (long x = 0; x < self->nc(); x++) {
+                        const dlib::rgb_pixel& pixel = (*self)(y, x);
+
+                        *(ptr + offset) = pixel.red;
+                        *(ptr + offset + 1) = pixel.green;
+                        *(ptr + offset + 2) = pixel.blue;
+                        offset += 3;
+                    }
+                }
+            })
+        }
+
+        RgbImage::from_raw(width as u32, height as u32, buffer).unwrap()
+    }
 }
 
 impl ImageMatrix {
+    /// The number of columns of the matrix.
+    pub fn width(&self) -> usize {
+        unsafe {
+            cpp!([self as "const dlib::matrix<dlib::rgb_pixel>*"] -> usize as "size_t" {
+                return self->nc();
+            })
+        }
+    }
+
+    /// The number of rows of the matrix.
+    pub fn height(&self) -> usize {
+        unsafe {
+            cpp!([self as "const dlib::matrix<dlib::rgb_pixel>*"] -> usize as "size_t" {
+                return self->nr();
+            })
+        }
+    }
+
     pub fn resize(&self, width: usize, height: usize) -> Self {
         unsafe {
             cpp!([self as "const dlib::matrix<dlib::rgb_pixel>*", width as "size_t", height as "size_t"] -> ImageMatrix as "dlib::matrix<dlib::rgb_pixel>" {
@@ -55,3 +101,13 @@ impl ImageMatrix {
         }
     }
 }
+
+#[test]
+fn test_image_round_trip() {
+    let image = RgbImage::from_fn(3, 2, |x, y| Rgb([x as u8, y as u8, 42]));
+    let matrix = ImageMatrix::from_image(&image);
+
+    assert_eq!(matrix.width(), 3);
+    assert_eq!(matrix.height(), 2);
+    assert_eq!(matrix.to_image(), image);
+}
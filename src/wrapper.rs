@@ -1,9 +1,15 @@
 cpp! {{
+    #include <dlib/clustering.h>
     #include <dlib/dnn.h>
     #include <dlib/image_processing/frontal_face_detector.h>
     #include <dlib/image_processing/full_object_detection.h>
     #include <dlib/image_transforms.h>
     #include <dlib/matrix/matrix_math_functions_abstract.h>
+    #include <dlib/svm_threaded.h>
+    #include <dlib/threads.h>
+
+    #include <atomic>
+    #include <sstream>
 
     // face encoding network definition from
     // https://github.com/davisking/dlib/blob/master/tools/python/src/face_recognition.cpp
@@ -49,16 +55,173 @@ cpp! {{
 
     // misc
 
-    // TODO: I am unsure if having rnd as a global here is thread safe.
+    // a face encoding tagged with the fingerprint of the model that generated it, 0 if unknown
+    // mirrors `FaceEncoding` in src/face_encoding/encoding.rs
+    struct face_encoding {
+        dlib::matrix<double,0,1> values;
+        uint64_t fingerprint = 0;
+    };
+
+    // FNV-1a hash of the serialized network
+    template <typename net_type>
+    uint64_t hash_network(net_type& net) {
+        std::ostringstream stream;
+        dlib::serialize(net, stream);
+
+        uint64_t hash = 0xcbf29ce484222325;
+        for (const unsigned char byte : stream.str()) {
+            hash ^= byte;
+            hash *= 0x100000001b3;
+        }
+
+        return hash;
+    }
+
+    // bounding box of a transformed rectangle, as done by `dlib::map_det_to_chip`
+    dlib::rectangle transform_rect(const dlib::point_transform_affine& tform, const dlib::rectangle& rect) {
+        dlib::rectangle out;
+        out += dlib::point(tform(rect.tl_corner()));
+        out += dlib::point(tform(rect.tr_corner()));
+        out += dlib::point(tform(rect.bl_corner()));
+        out += dlib::point(tform(rect.br_corner()));
+        return out;
+    }
+
+    // mirrors `JitterOptions` in src/face_encoding/options.rs
+    struct jitter_options {
+        double max_rotation_degrees;
+        double min_scale;
+        double max_scale;
+        double max_translation;
+        double flip_probability;
+    };
+
+    // `dlib::jitter_image` with configurable ranges
+    // https://github.com/davisking/dlib/blob/master/dlib/image_transforms/interpolation.h
+    dlib::matrix<dlib::rgb_pixel> jitter_image(const dlib::matrix<dlib::rgb_pixel>& img, const jitter_options& options, dlib::rand& rnd) {
+        const auto rect = dlib::shrink_rect(dlib::get_rect(img), 3);
 
-    dlib::rand rnd;
+        // perturb the location of the crop by a small fraction of the object's size.
+        const dlib::point rand_translate = dlib::dpoint(
+            rnd.get_double_in_range(-options.max_translation, options.max_translation) * rect.width(),
+            rnd.get_double_in_range(-options.max_translation, options.max_translation) * rect.height());
+
+        // perturb the scale of the crop by a fraction of the object's size
+        const double rand_scale_perturb = rnd.get_double_in_range(options.min_scale, options.max_scale);
+
+        const long box_size = rect.height() / rand_scale_perturb;
+        const auto crop_rect = dlib::centered_rect(dlib::center(rect) + rand_translate, box_size, box_size);
+        const double angle = rnd.get_double_in_range(-options.max_rotation_degrees, options.max_rotation_degrees) * dlib::pi / 180;
+
+        dlib::matrix<dlib::rgb_pixel> crop;
+        dlib::extract_image_chip(img, dlib::chip_details(crop_rect, dlib::chip_dims(img.nr(), img.nc()), angle), crop);
+        if (rnd.get_random_double() > 1.0 - options.flip_probability) {
+            dlib::flip_image_left_right(crop);
+        }
+
+        return crop;
+    }
 
     // https://github.com/davisking/dlib/blob/master/tools/python/src/face_recognition.cpp#L131
-    std::vector<dlib::matrix<dlib::rgb_pixel>> jitter_image(const dlib::matrix<dlib::rgb_pixel>& img, const uint32_t num_jitters) {
+    //
+    // every image gets its own random number generator, so the crops only depend on the image and the seed
+    std::vector<dlib::matrix<dlib::rgb_pixel>> jitter_image(const dlib::matrix<dlib::rgb_pixel>& img, const uint32_t num_jitters, const jitter_options& options, const uint64_t seed) {
+        dlib::rand rnd;
+        rnd.set_seed(std::to_string(seed));
+
         std::vector<dlib::matrix<dlib::rgb_pixel>> crops;
         for (uint32_t i = 0; i < num_jitters; ++i) {
-            crops.push_back(dlib::jitter_image(img, rnd));
+            crops.push_back(jitter_image(img, options, rnd));
         }
         return crops;
     }
+
+    // mirrors `Averaging::Median` in src/face_encoding/options.rs, any other value averages with the mean
+    const uint32_t AVERAGING_MEDIAN = 1;
+
+    dlib::matrix<float,0,1> average_encodings(std::vector<dlib::matrix<float,0,1>>& encodings, const uint32_t averaging) {
+        if (averaging == AVERAGING_MEDIAN) {
+            dlib::matrix<float,0,1> median(encodings.front().size());
+
+            std::vector<float> values(encodings.size());
+            for (long row = 0; row < median.size(); row++) {
+                for (size_t offset = 0; offset < encodings.size(); offset++) {
+                    values[offset] = encodings[offset](row);
+                }
+
+                const auto middle = values.begin() + values.size() / 2;
+                std::nth_element(values.begin(), middle, values.end());
+                if (values.size() % 2 == 0) {
+                    median(row) = (*middle + *std::max_element(values.begin(), middle)) / 2;
+                } else {
+                    median(row) = *middle;
+                }
+            }
+
+            return median;
+        }
+
+        return dlib::mean(dlib::mat(encodings));
+    }
+
+    // add a horizontally mirrored copy of every crop
+    void mirror_images(std::vector<dlib::matrix<dlib::rgb_pixel>>& crops) {
+        const size_t num_crops = crops.size();
+        for (size_t offset = 0; offset < num_crops; offset++) {
+            dlib::matrix<dlib::rgb_pixel> mirrored;
+            dlib::flip_image_left_right(crops[offset], mirrored);
+            crops.push_back(std::move(mirrored));
+        }
+    }
+
+    // extract descriptors from aligned face chips
+    std::vector<dlib::matrix<float,0,1>> encode_face_chips(
+        face_encoding_nn& net,
+        const dlib::matrix<dlib::rgb_pixel>* chips,
+        const size_t num_chips,
+        const uint32_t num_jitters,
+        const size_t batch_size,
+        const jitter_options& jitter,
+        const uint32_t averaging,
+        const uint64_t seed,
+        const bool flip
+    ) {
+        std::vector<dlib::matrix<float,0,1>> encodings(num_chips);
+
+        if (num_jitters <= 1 && !flip) {
+            for (size_t offset = 0; offset < num_chips; offset += batch_size) {
+                const size_t end = std::min(offset + batch_size, num_chips);
+                net(chips + offset, chips + end, encodings.begin() + offset);
+            }
+        } else {
+            for (size_t offset = 0; offset < num_chips; offset++) {
+                std::vector<dlib::matrix<dlib::rgb_pixel>> crops;
+                if (num_jitters <= 1) {
+                    crops.push_back(chips[offset]);
+                } else {
+                    crops = jitter_image(chips[offset], num_jitters, jitter, seed);
+                }
+                if (flip) {
+                    mirror_images(crops);
+                }
+
+                auto network_output = net(crops, batch_size);
+                encodings[offset] = average_encodings(network_output, averaging);
+            }
+        }
+
+        return encodings;
+    }
+
+    // convert encodings from float vectors to double vectors
+    std::vector<face_encoding> cast_encodings(const std::vector<dlib::matrix<float,0,1>>& float_encodings, const uint64_t fingerprint) {
+        std::vector<face_encoding> encodings;
+        encodings.reserve(float_encodings.size());
+
+        for (auto& float_encoding : float_encodings) {
+            encodings.push_back(face_encoding { dlib::matrix_cast<double>(float_encoding), fingerprint });
+        }
+
+        return encodings;
+    }
 }}
@@ -27,8 +27,10 @@ lazy_static! {
     // Data
     static ref OBAMA_1: RgbImage = load_image("obama_1.jpg");
     static ref OBAMA_2: RgbImage = load_image("obama_2.jpg");
+    static ref HILLARY_1: RgbImage = load_image("hillary_1.jpg");
     static ref OBAMA_1_MATRIX: ImageMatrix = ImageMatrix::from_image(&OBAMA_1);
     static ref OBAMA_2_MATRIX: ImageMatrix = ImageMatrix::from_image(&OBAMA_2);
+    static ref HILLARY_1_MATRIX: ImageMatrix = ImageMatrix::from_image(&HILLARY_1);
 }
 
 #[cfg(feature = "embed-all")]
@@ -39,9 +41,11 @@ fn initialize() {
     lazy_static::initialize(&MODEL);
     lazy_static::initialize(&OBAMA_1);
     lazy_static::initialize(&OBAMA_2);
+    lazy_static::initialize(&HILLARY_1);
 
     lazy_static::initialize(&OBAMA_1_MATRIX);
     lazy_static::initialize(&OBAMA_2_MATRIX);
+    lazy_static::initialize(&HILLARY_1_MATRIX);
 }
 
 #[cfg(not(feature = "embed-all"))]
@@ -105,3 +109,384 @@ fn encoding_distances() {
     let distance = a_encoding.distance(b_encoding);
     assert!(distance > 0.0 && distance < 0.6);
 }
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_face_chip_extraction() {
+    initialize();
+
+    let rect = DETECTOR.face_locations(&OBAMA_1_MATRIX)[0];
+    let landmarks = PREDICTOR.face_landmarks(&OBAMA_1_MATRIX, &rect);
+
+    let chip = FaceChip::extract(&OBAMA_1_MATRIX, &landmarks, 200, 0.5).unwrap();
+
+    assert_eq!(chip.details().width(), 200);
+    assert_eq!(chip.details().height(), 200);
+    assert_eq!(chip.to_image().dimensions(), (200, 200));
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_face_chip_mapping() {
+    initialize();
+
+    let rect = DETECTOR.face_locations(&OBAMA_1_MATRIX)[0];
+    let landmarks = PREDICTOR.face_landmarks(&OBAMA_1_MATRIX, &rect);
+    let chip = FaceChip::extract(&OBAMA_1_MATRIX, &landmarks, 150, 0.25).unwrap();
+
+    let to_chip = chip.details().mapping_to_chip();
+    let from_chip = chip.details().mapping_from_chip();
+
+    for (original, mapped) in landmarks
+        .iter()
+        .zip(to_chip.transform_landmarks(&landmarks).iter())
+    {
+        assert!((0..150).contains(&mapped.x()) && (0..150).contains(&mapped.y()));
+
+        let restored = from_chip.transform_point(mapped);
+        assert!((restored.x() - original.x()).abs() <= 2);
+        assert!((restored.y() - original.y()).abs() <= 2);
+    }
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_face_encoding_from_external_landmarks() {
+    initialize();
+
+    let rect = DETECTOR.face_locations(&OBAMA_1_MATRIX)[0];
+    let predicted = PREDICTOR.face_landmarks(&OBAMA_1_MATRIX, &rect);
+    let external = FaceLandmarks::new(predicted.rect(), &predicted).unwrap();
+
+    let a = &MODEL.get_face_encodings(&OBAMA_1_MATRIX, &[predicted], 0)[0];
+    let b = &MODEL.get_face_encodings(&OBAMA_1_MATRIX, &[external], 0)[0];
+
+    assert_eq!(a, b);
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_face_landmark_batch_detection() {
+    initialize();
+
+    let locations = DETECTOR.face_locations(&OBAMA_1_MATRIX);
+
+    let batch = PREDICTOR.face_landmarks_batch(&OBAMA_1_MATRIX, &locations);
+    let parallel = PREDICTOR.face_landmarks_batch_parallel(&OBAMA_1_MATRIX, &locations, 4);
+
+    assert_eq!(batch.len(), locations.len());
+    for ((rect, a), b) in locations.iter().zip(&batch).zip(&parallel) {
+        let single = PREDICTOR.face_landmarks(&OBAMA_1_MATRIX, rect);
+
+        assert_eq!(&**a, &*single);
+        assert_eq!(&**b, &*single);
+    }
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_face_encoding_from_chips() {
+    initialize();
+
+    let rect = DETECTOR.face_locations(&OBAMA_1_MATRIX)[0];
+    let landmarks = PREDICTOR.face_landmarks(&OBAMA_1_MATRIX, &rect);
+    let chip = FaceChip::extract(
+        &OBAMA_1_MATRIX,
+        &landmarks,
+        FaceChip::DEFAULT_SIZE,
+        FaceChip::DEFAULT_PADDING,
+    )
+    .unwrap();
+
+    let from_landmarks = &MODEL.get_face_encodings(&OBAMA_1_MATRIX, &[landmarks], 0)[0];
+    let from_chips = MODEL
+        .get_face_encodings_from_chips(&[chip.image().clone()], 0)
+        .unwrap();
+
+    assert_eq!(from_chips.len(), 1);
+    assert!(from_landmarks.distance(&from_chips[0]) < 1e-6);
+
+    let resized = chip.image().resize(100, 100);
+    assert_eq!(
+        MODEL.get_face_encodings_from_chips(&[resized], 0).err(),
+        Some(ChipSizeError {
+            index: 0,
+            width: 100,
+            height: 100
+        })
+    );
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_face_encoding_batch() {
+    initialize();
+
+    let a: &ImageMatrix = &OBAMA_1_MATRIX;
+    let b: &ImageMatrix = &OBAMA_2_MATRIX;
+
+    let a_landmarks = PREDICTOR.face_landmarks(&a, &DETECTOR.face_locations(&a)[0]);
+    let b_landmarks = PREDICTOR.face_landmarks(&b, &DETECTOR.face_locations(&b)[0]);
+
+    let batch = MODEL.get_face_encodings_batch(&[(a, &a_landmarks), (b, &b_landmarks)], 0, 1);
+
+    let a_encoding = &MODEL.get_face_encodings(&a, &[a_landmarks], 0)[0];
+    let b_encoding = &MODEL.get_face_encodings(&b, &[b_landmarks], 0)[0];
+
+    assert_eq!(batch.len(), 2);
+    assert!(batch[0].distance(a_encoding) < 1e-6);
+    assert!(batch[1].distance(b_encoding) < 1e-6);
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_face_encoding_options() {
+    initialize();
+
+    let a = &OBAMA_1_MATRIX;
+    let b = &OBAMA_2_MATRIX;
+
+    let a_landmarks = PREDICTOR.face_landmarks(&a, &DETECTOR.face_locations(&a)[0]);
+    let b_landmarks = PREDICTOR.face_landmarks(&b, &DETECTOR.face_locations(&b)[0]);
+
+    let model = MODEL
+        .clone()
+        .with_options(EncodingOptions {
+            jitter: JitterOptions {
+                max_rotation_degrees: 5.0,
+                flip_probability: 0.0,
+                ..Default::default()
+            },
+            averaging: Averaging::Median,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let a_encoding = &model.get_face_encodings(&a, &[a_landmarks], 10)[0];
+    let b_encoding = &model.get_face_encodings(&b, &[b_landmarks], 10)[0];
+
+    let distance = a_encoding.distance(b_encoding);
+    assert!(distance > 0.0 && distance < 0.6);
+
+    // settings out of range are rejected, keeping the current ones
+    let mut rejected = model.clone();
+    assert_eq!(
+        rejected.set_options(EncodingOptions {
+            padding: f64::NAN,
+            ..Default::default()
+        }),
+        Err(EncodingOptionsError::Padding)
+    );
+    assert_eq!(rejected.options(), model.options());
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_face_encoding_jitter_is_deterministic() {
+    initialize();
+
+    let rect = DETECTOR.face_locations(&OBAMA_1_MATRIX)[0];
+    let landmarks = PREDICTOR.face_landmarks(&OBAMA_1_MATRIX, &rect);
+    let chip = FaceChip::extract(
+        &OBAMA_1_MATRIX,
+        &landmarks,
+        FaceChip::DEFAULT_SIZE,
+        FaceChip::DEFAULT_PADDING,
+    )
+    .unwrap()
+    .image()
+    .clone();
+
+    let model = MODEL
+        .clone()
+        .with_options(EncodingOptions {
+            seed: 42,
+            ..Default::default()
+        })
+        .unwrap();
+    let expected = model
+        .get_face_encodings_from_chips(&[chip.clone()], 5)
+        .unwrap()[0]
+        .clone();
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let model = model.clone();
+            let chip = chip.clone();
+            std::thread::spawn(move || {
+                model.get_face_encodings_from_chips(&[chip], 5).unwrap()[0].clone()
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), expected);
+    }
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn flip_augmentation_separates_identities() {
+    initialize();
+
+    let images: [&ImageMatrix; 3] = [&OBAMA_1_MATRIX, &OBAMA_2_MATRIX, &HILLARY_1_MATRIX];
+    let landmarks: Vec<_> = images
+        .iter()
+        .map(|image| PREDICTOR.face_landmarks(image, &DETECTOR.face_locations(image)[0]))
+        .collect();
+    let faces: Vec<_> = images.iter().copied().zip(&landmarks).collect();
+    // every pair of faces, labeled whether both show the same person
+    let pairs = [(0, 1, true), (0, 2, false), (1, 2, false)];
+
+    // the distance between the closest pair of different people and the farthest pair of the same person
+    let margin = |flip: bool| {
+        let model = MODEL
+            .clone()
+            .with_options(EncodingOptions {
+                flip,
+                ..Default::default()
+            })
+            .unwrap();
+        let encodings = model.get_face_encodings_batch(&faces, 0, 16);
+
+        let distances = pairs.map(|(a, b, same)| (encodings[a].distance(&encodings[b]), same));
+        let genuine = distances
+            .iter()
+            .filter(|(_, same)| *same)
+            .map(|(distance, _)| *distance)
+            .fold(f64::MIN, f64::max);
+        let impostor = distances
+            .iter()
+            .filter(|(_, same)| !*same)
+            .map(|(distance, _)| *distance)
+            .fold(f64::MAX, f64::min);
+
+        assert!(genuine < 0.6);
+        assert!(impostor > 0.6);
+        impostor - genuine
+    };
+
+    assert!(margin(true) >= margin(false));
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_face_encoding_f32() {
+    initialize();
+
+    let rect = DETECTOR.face_locations(&OBAMA_1_MATRIX)[0];
+    let landmarks = PREDICTOR.face_landmarks(&OBAMA_1_MATRIX, &rect);
+
+    let encoding_f32 = MODEL.get_face_encodings_f32(&OBAMA_1_MATRIX, &[landmarks.clone()], 0);
+    let encoding = &MODEL.get_face_encodings(&OBAMA_1_MATRIX, &[landmarks], 0)[0];
+
+    assert_eq!(encoding_f32.len(), 1);
+    assert_eq!(
+        encoding_f32[0],
+        FaceEncodingF32::try_from(encoding).unwrap()
+    );
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_face_encoding_fingerprint() {
+    initialize();
+
+    let rect = DETECTOR.face_locations(&OBAMA_1_MATRIX)[0];
+    let landmarks = PREDICTOR.face_landmarks(&OBAMA_1_MATRIX, &rect);
+
+    let encoding = &MODEL.get_face_encodings(&OBAMA_1_MATRIX, &[landmarks.clone()], 0)[0];
+
+    assert_eq!(encoding.fingerprint(), Some(MODEL.fingerprint()));
+    assert_eq!(
+        FaceEncoding::from_bytes(&encoding.to_bytes()).unwrap(),
+        *encoding
+    );
+
+    let flipped = MODEL
+        .clone()
+        .with_options(EncodingOptions {
+            flip: true,
+            ..Default::default()
+        })
+        .unwrap();
+    let other = &flipped.get_face_encodings(&OBAMA_1_MATRIX, &[landmarks], 0)[0];
+
+    assert_ne!(flipped.fingerprint(), MODEL.fingerprint());
+    assert!(encoding.try_distance(other).is_err());
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_face_clustering() {
+    initialize();
+
+    let images: [&ImageMatrix; 3] = [&OBAMA_1_MATRIX, &HILLARY_1_MATRIX, &OBAMA_2_MATRIX];
+    let landmarks: Vec<_> = images
+        .iter()
+        .map(|image| PREDICTOR.face_landmarks(image, &DETECTOR.face_locations(image)[0]))
+        .collect();
+    let faces: Vec<_> = images.iter().copied().zip(&landmarks).collect();
+    let encodings = MODEL.get_face_encodings_batch(&faces, 0, 16);
+
+    let labels = ChineseWhispers::default().cluster(&encodings).unwrap();
+
+    assert_eq!(labels[0], labels[2]);
+    assert_ne!(labels[0], labels[1]);
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_image_deduplication() {
+    initialize();
+
+    // the same photo uploaded twice, next to another photo of the same person
+    let images: [&ImageMatrix; 4] = [
+        &OBAMA_1_MATRIX,
+        &HILLARY_1_MATRIX,
+        &OBAMA_2_MATRIX,
+        &OBAMA_1_MATRIX,
+    ];
+    let groups = Deduplicator::default()
+        .group_images(&*DETECTOR, &*PREDICTOR, &*MODEL, images)
+        .unwrap();
+
+    assert_eq!(
+        groups,
+        vec![DuplicateGroup {
+            images: vec![0, 3],
+            canonical: 0,
+        }]
+    );
+}
+
+#[cfg(feature = "embed-all")]
+#[test]
+fn test_svm_classifier() {
+    initialize();
+
+    let images: [&ImageMatrix; 3] = [&OBAMA_1_MATRIX, &HILLARY_1_MATRIX, &OBAMA_2_MATRIX];
+    let landmarks: Vec<_> = images
+        .iter()
+        .map(|image| PREDICTOR.face_landmarks(image, &DETECTOR.face_locations(image)[0]))
+        .collect();
+
+    let faces: Vec<_> = images.iter().copied().zip(&landmarks).collect();
+    let encodings = MODEL.get_face_encodings_batch(&faces, 0, 16);
+
+    // train on the first photo of every person
+    let labels = ["obama", "hillary"];
+    let classifier = SvmTrainer::default()
+        .train(&encodings[..2], &labels)
+        .unwrap();
+    assert_eq!(classifier.labels(), ["hillary", "obama"]);
+
+    let (label, _) = classifier.predict(&encodings[2]).unwrap();
+    assert_eq!(label, "obama");
+    assert_eq!(classifier.decision_scores(&encodings[2]).len(), 2);
+
+    let path = std::env::temp_dir().join("dlib_face_recognition_svm.dfrs");
+    classifier.save(&path).unwrap();
+    assert_eq!(SvmClassifier::load(&path).unwrap(), classifier);
+    std::fs::remove_file(&path).unwrap();
+}
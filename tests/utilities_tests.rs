@@ -1,4 +1,9 @@
-use dlib_face_recognition::FaceEncoding;
+use dlib_face_recognition::{
+    Agglomerative, BruteForceIndex, Calibration, CalibrationError, ChineseWhispers, ClusterQuality,
+    Dbscan, DecodeError, Deduplicator, DuplicateGroup, ENCODING_MODEL_ID, FaceComparer,
+    FaceEncoding, FaceEncodingF32, Gallery, GalleryEvent, HnswIndex, Metric, ModelFingerprint,
+    PersistenceError, PersistentComparer, Scoring, SharedGallery, SvmClassifier,
+};
 
 #[test]
 fn encoding_transformation() {
@@ -13,3 +18,533 @@ fn invalid_array_size() {
 
     assert_eq!(true, FaceEncoding::from_vec(&original_array).is_err());
 }
+
+#[test]
+fn comparer_with_metric() {
+    let mut comparer = FaceComparer::default().with_metric(Metric::Cosine);
+    comparer
+        .insert("a".to_string(), FaceEncoding::new_from_scalar(1.0))
+        .unwrap();
+
+    // the encodings point in the same direction, so only their euclidean distance is large
+    let face = FaceEncoding::new_from_scalar(2.0);
+    let key = comparer.find(&face).unwrap();
+    assert_eq!(comparer.get_name_unchecked(&key), "a");
+
+    let mut comparer = FaceComparer::default();
+    comparer
+        .insert("a".to_string(), FaceEncoding::new_from_scalar(1.0))
+        .unwrap();
+    assert_eq!(comparer.find(&face), None);
+}
+
+#[test]
+fn comparer_rejects_other_models() {
+    let fingerprint = ModelFingerprint::from_u64(1);
+    let other = ModelFingerprint::from_u64(2);
+
+    let mut comparer = FaceComparer::default();
+    comparer
+        .insert(
+            "a".to_string(),
+            FaceEncoding::new_from_scalar(1.0).with_fingerprint(fingerprint),
+        )
+        .unwrap();
+    assert_eq!(comparer.fingerprint(), fingerprint);
+
+    let face = FaceEncoding::new_from_scalar(1.0).with_fingerprint(other);
+    assert!(comparer.try_find(&face).is_err());
+    assert!(comparer.try_find_k(&face, 1).is_err());
+    assert!(comparer.insert("b".to_string(), face.clone()).is_err());
+    assert_eq!(comparer.len(), 1);
+
+    // the infallible lookups treat faces of other models as unknown
+    assert_eq!(comparer.find(&face), None);
+    assert!(comparer.find_k(&face, 1).is_empty());
+
+    // encodings without a fingerprint can be compared with any model
+    let face = FaceEncoding::new_from_scalar(1.0);
+    assert_eq!(comparer.try_find(&face), Ok(Some(0)));
+}
+
+#[test]
+fn encoding_binary_round_trip() {
+    let encoding = FaceEncoding::from_vec(&vec![0.125; 128]).unwrap();
+
+    let bytes = encoding.to_bytes();
+    assert_eq!(bytes.len(), 17 + ENCODING_MODEL_ID.len() + 128 * 8);
+    assert_eq!(FaceEncoding::from_bytes(&bytes).unwrap(), encoding);
+
+    let text = FaceEncodingF32::try_from(&encoding).unwrap().to_base64();
+    assert_eq!(FaceEncoding::from_base64(&text).unwrap(), encoding);
+
+    assert_eq!(
+        FaceEncoding::from_bytes(&bytes[..20]).err(),
+        Some(DecodeError::Truncated)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn encoding_serde_round_trip() {
+    let encoding = FaceEncoding::from_vec(&vec![0.5; 128]).unwrap();
+
+    let json = serde_json::to_string(&encoding).unwrap();
+    assert_eq!(
+        serde_json::from_str::<FaceEncoding>(&json).unwrap(),
+        encoding
+    );
+
+    assert!(serde_json::from_str::<FaceEncoding>("[0.5, 0.5]").is_err());
+}
+
+#[test]
+fn comparer_with_templates() {
+    let mut comparer = FaceComparer::default()
+        .with_scoring(Scoring::Centroid)
+        .with_outlier_rejection(0.6);
+
+    for scalar in [0.0, 0.04] {
+        let accepted = comparer
+            .enroll("a".to_string(), FaceEncoding::new_from_scalar(scalar))
+            .unwrap();
+        assert!(accepted);
+    }
+    let outlier = FaceEncoding::new_from_scalar(1.0);
+    assert_eq!(comparer.enroll("a".to_string(), outlier), Ok(false));
+    assert_eq!(comparer.len(), 1);
+
+    let key = comparer.find(&FaceEncoding::new_from_scalar(0.02)).unwrap();
+    let template = comparer.template(&key).unwrap();
+    assert_eq!(template.len(), 2);
+    assert_eq!(
+        template.centroid(),
+        Some(&FaceEncoding::new_from_scalar(0.02))
+    );
+
+    // inserting replaces all encodings of a person
+    comparer
+        .insert("a".to_string(), FaceEncoding::new_from_scalar(1.0))
+        .unwrap();
+    assert_eq!(comparer.template(&key).unwrap().len(), 1);
+}
+
+#[test]
+fn comparer_find_k() {
+    let mut comparer = FaceComparer::default().with_tolerance(0.5);
+    for (name, scalar) in [("a", 0.0), ("b", 0.03), ("c", 1.0)] {
+        comparer
+            .insert(name.to_string(), FaceEncoding::new_from_scalar(scalar))
+            .unwrap();
+    }
+
+    let face = FaceEncoding::new_from_scalar(0.01);
+    let matches = comparer.find_k(&face, 2);
+    assert_eq!(matches.len(), 2);
+    assert_eq!((matches[0].name, matches[1].name), ("a", "b"));
+    assert!(matches[0].distance < matches[1].distance);
+    let key = matches[0].key;
+    assert_eq!(comparer.find(&face), Some(key));
+    assert_eq!(comparer.find_k(&face, 5).len(), 3);
+
+    // "a" and "b" are both close to the face, so the match is ambiguous
+    let comparer = comparer.with_margin(0.2);
+    assert_eq!(comparer.find(&face), None);
+    assert_eq!(
+        comparer.find(&FaceEncoding::new_from_scalar(-0.02)),
+        Some(key)
+    );
+}
+
+#[test]
+fn comparer_persistence() {
+    let directory = std::env::temp_dir().join(format!("dfr-persistence-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("gallery.dfrg");
+
+    let mut comparer = PersistentComparer::open(&path, FaceComparer::default()).unwrap();
+    comparer
+        .insert("a".to_string(), FaceEncoding::new_from_scalar(0.0))
+        .unwrap();
+    comparer
+        .insert("b".to_string(), FaceEncoding::new_from_scalar(1.0))
+        .unwrap();
+    comparer.snapshot().unwrap();
+
+    // changes after the snapshot are only in the journal
+    let face = FaceEncoding::new_from_scalar(2.0);
+    assert!(comparer.enroll("b".to_string(), face.clone()).unwrap());
+    comparer.remove_name("a").unwrap();
+    let key = comparer.find(&face).unwrap();
+    drop(comparer);
+
+    // a partially written record is discarded
+    let journal = directory.join("gallery.dfrg.journal");
+    let mut bytes = std::fs::read(&journal).unwrap();
+    bytes.extend_from_slice(&[200, 0, 0, 0, 1]);
+    std::fs::write(&journal, bytes).unwrap();
+
+    let mut comparer = PersistentComparer::open(&path, FaceComparer::default()).unwrap();
+    assert_eq!(comparer.len(), 1);
+    assert_eq!(comparer.find(&face), Some(key));
+    assert_eq!(comparer.template(&key).unwrap().len(), 2);
+    drop(comparer);
+
+    // a damaged record followed by intact ones is not discarded along with them
+    let bytes = std::fs::read(&journal).unwrap();
+    let mut damaged = bytes.clone();
+    damaged[10] ^= 1;
+    std::fs::write(&journal, damaged).unwrap();
+    assert!(matches!(
+        PersistentComparer::open(&path, FaceComparer::default()),
+        Err(PersistenceError::ChecksumMismatch)
+    ));
+    std::fs::write(&journal, bytes).unwrap();
+
+    let mut comparer = PersistentComparer::open(&path, FaceComparer::default()).unwrap();
+    assert_eq!(comparer.template(&key).unwrap().len(), 2);
+
+    // keys are not reused after a reload
+    comparer
+        .insert("c".to_string(), FaceEncoding::new_from_scalar(-1.0))
+        .unwrap();
+    let c = comparer.find(&FaceEncoding::new_from_scalar(-1.0)).unwrap();
+    assert_eq!(c, 2);
+    comparer.snapshot().unwrap();
+
+    let comparer = FaceComparer::load(&path).unwrap();
+    assert_eq!(comparer.len(), 2);
+    assert_eq!(comparer.get_name_unchecked(&c), "c");
+    // the key of the removed person stays unassigned
+    assert_eq!(comparer.get_name(&0), None);
+
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[10] ^= 1;
+    std::fs::write(&path, bytes).unwrap();
+    assert!(matches!(
+        FaceComparer::load(&path),
+        Err(PersistenceError::ChecksumMismatch)
+    ));
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn comparer_with_index() {
+    let faces: Vec<_> = (0..50)
+        .map(|i| FaceEncoding::new_from_scalar(i as f64 * 0.1))
+        .collect();
+
+    let mut exact = FaceComparer::default();
+    let mut indexed = FaceComparer::default().with_index(HnswIndex::default());
+    for (i, face) in faces.iter().enumerate() {
+        exact.insert(i.to_string(), face.clone()).unwrap();
+        indexed.insert(i.to_string(), face.clone()).unwrap();
+    }
+
+    let face = FaceEncoding::new_from_scalar(1.22);
+    assert_eq!(indexed.find_k(&face, 3), exact.find_k(&face, 3));
+    assert_eq!(indexed.find(&face), exact.find(&face));
+
+    indexed.remove_name("12");
+    assert_eq!(indexed.find_k(&face, 1)[0].name, "13");
+
+    // people stored before the index is added are indexed as well
+    let indexed = exact.with_index(BruteForceIndex::new());
+    assert_eq!(indexed.find_k(&face, 1)[0].name, "12");
+
+    // for the cosine metrics, the index ranks the encodings scaled to unit length,
+    // so a person pointing in the same direction is found despite many closer people
+    let encoding = |values: &[(usize, f64)]| {
+        let mut encoding = vec![0.0; 128];
+        for &(i, value) in values {
+            encoding[i] = value;
+        }
+        FaceEncoding::from_vec(&encoding).unwrap()
+    };
+    let mut indexed = FaceComparer::default().with_index(BruteForceIndex::new());
+    indexed
+        .insert("far".to_string(), encoding(&[(0, 10.0)]))
+        .unwrap();
+    for i in 1..=30 {
+        indexed
+            .insert(i.to_string(), encoding(&[(0, 0.1), (i, 0.1)]))
+            .unwrap();
+    }
+    let indexed = indexed.with_metric(Metric::Cosine);
+    assert_eq!(indexed.find(&encoding(&[(0, 0.1)])), Some(0));
+}
+
+#[test]
+fn chinese_whispers_clustering() {
+    let encodings: Vec<_> = [0.0, 1.0, 0.01, 1.02, 3.0]
+        .iter()
+        .map(|&scalar| FaceEncoding::new_from_scalar(scalar))
+        .collect();
+
+    let labels = ChineseWhispers::default().cluster(&encodings).unwrap();
+
+    assert_eq!(labels.len(), 5);
+    assert_eq!(labels[0], labels[2]);
+    assert_eq!(labels[1], labels[3]);
+    assert_ne!(labels[0], labels[1]);
+    assert!(labels[4] != labels[0] && labels[4] != labels[1]);
+    assert!(labels.iter().all(|&label| label < 3));
+}
+
+#[test]
+fn density_and_hierarchical_clustering() {
+    let encodings: Vec<_> = [0.0, 0.01, 0.02, 1.0, 1.01, 1.02, 3.0]
+        .iter()
+        .map(|&scalar| FaceEncoding::new_from_scalar(scalar))
+        .collect();
+
+    let labels = Dbscan::default().cluster(&encodings).unwrap();
+    assert_eq!(
+        labels,
+        vec![Some(0), Some(0), Some(0), Some(1), Some(1), Some(1), None]
+    );
+
+    let dendrogram = Agglomerative::default().dendrogram(&encodings).unwrap();
+    assert_eq!(dendrogram.merges().len(), 6);
+    assert_eq!(dendrogram.cut(0.6), vec![0, 0, 0, 1, 1, 1, 2]);
+    assert_eq!(
+        Agglomerative::default().cluster(&encodings).unwrap(),
+        dendrogram.cut(0.6)
+    );
+
+    let quality = ClusterQuality::evaluate(&encodings, &labels, Metric::Euclidean).unwrap();
+    assert!(quality.silhouette.unwrap() > 0.9);
+    assert!(quality.mean_intra_distance.unwrap() < quality.mean_inter_distance.unwrap());
+}
+
+#[test]
+fn gallery_with_metadata() {
+    #[derive(Debug, PartialEq)]
+    struct Person {
+        name: &'static str,
+        employee: bool,
+    }
+
+    let mut gallery: Gallery<u64, Person> = Gallery::new();
+    let alice = Person {
+        name: "alice",
+        employee: true,
+    };
+    let bob = Person {
+        name: "bob",
+        employee: false,
+    };
+    assert_eq!(
+        gallery.insert(1001, FaceEncoding::new_from_scalar(0.0), alice),
+        Ok(None)
+    );
+    gallery
+        .insert(2002, FaceEncoding::new_from_scalar(1.0), bob)
+        .unwrap();
+    assert_eq!(gallery.len(), 2);
+
+    let (key, person) = gallery.find(&FaceEncoding::new_from_scalar(0.01)).unwrap();
+    assert_eq!((*key, person.name), (1001, "alice"));
+    assert!(person.employee);
+    assert_eq!(gallery.find(&FaceEncoding::new_from_scalar(0.5)), None);
+
+    let matches = gallery.find_k(&FaceEncoding::new_from_scalar(0.9), 2);
+    assert_eq!(*matches[0].key, 2002);
+    assert_eq!(matches[1].metadata.name, "alice");
+
+    assert_eq!(
+        gallery.enroll(&1001, FaceEncoding::new_from_scalar(0.02)),
+        Ok(Some(true))
+    );
+    assert_eq!(
+        gallery.enroll(&3003, FaceEncoding::new_from_scalar(0.02)),
+        Ok(None)
+    );
+    assert_eq!(gallery.template(&1001).unwrap().len(), 2);
+
+    gallery.get_mut(&2002).unwrap().employee = true;
+    assert_eq!(
+        gallery.remove(&2002).map(|person| person.employee),
+        Some(true)
+    );
+    assert_eq!(gallery.get(&2002), None);
+    assert!(!gallery.contains_key(&2002));
+
+    // faces of other models match nobody instead of panicking
+    let mut gallery: Gallery<u64, ()> = Gallery::new();
+    let tagged = |model| {
+        FaceEncoding::new_from_scalar(0.0).with_fingerprint(ModelFingerprint::from_u64(model))
+    };
+    gallery.insert(1, tagged(1), ()).unwrap();
+    let face = tagged(2);
+    assert!(gallery.try_find(&face).is_err());
+    assert_eq!(gallery.find(&face), None);
+    assert!(gallery.find_k(&face, 1).is_empty());
+}
+
+#[test]
+fn shared_gallery_across_threads() {
+    let gallery: SharedGallery<u32, String> = SharedGallery::default();
+    let events = gallery.subscribe();
+
+    gallery
+        .insert(1, FaceEncoding::new_from_scalar(0.0), "a".to_string())
+        .unwrap();
+    let before = gallery.snapshot();
+
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..100 {
+                    let (key, name) = gallery.find(&FaceEncoding::new_from_scalar(0.01)).unwrap();
+                    assert_eq!((key, name.as_str()), (1, "a"));
+                }
+            });
+        }
+
+        scope.spawn(|| {
+            for key in 2..10 {
+                gallery
+                    .insert(
+                        key,
+                        FaceEncoding::new_from_scalar(key as f64),
+                        key.to_string(),
+                    )
+                    .unwrap();
+            }
+        });
+    });
+
+    // snapshots are not affected by later changes
+    assert_eq!(before.len(), 1);
+    assert_eq!(gallery.len(), 9);
+
+    assert_eq!(
+        gallery.enroll(&1, FaceEncoding::new_from_scalar(0.02)),
+        Ok(Some(true))
+    );
+    assert_eq!(gallery.remove(&9), Some("9".to_string()));
+    assert_eq!(gallery.remove(&9), None);
+
+    let events: Vec<_> = events.try_iter().collect();
+    assert_eq!(events.len(), 11);
+    assert_eq!(events[0], GalleryEvent::Inserted(1));
+    assert_eq!(events[9], GalleryEvent::Enrolled(1));
+    assert_eq!(events[10], GalleryEvent::Removed(9));
+}
+
+#[test]
+fn image_deduplication() {
+    let faces = |scalars: &[f64]| -> Vec<FaceEncoding> {
+        scalars
+            .iter()
+            .map(|&scalar| FaceEncoding::new_from_scalar(scalar))
+            .collect()
+    };
+    let images = [
+        faces(&[0.0]),
+        faces(&[1.0]),
+        faces(&[0.01]),
+        faces(&[]),
+        faces(&[1.005, 3.0]),
+        faces(&[0.05]),
+        faces(&[0.015]),
+    ];
+
+    let groups = Deduplicator::default().group(&images).unwrap();
+
+    assert_eq!(
+        groups,
+        vec![
+            DuplicateGroup {
+                images: vec![0, 2, 6],
+                canonical: 2,
+            },
+            DuplicateGroup {
+                images: vec![1, 4],
+                canonical: 1,
+            },
+        ]
+    );
+}
+
+#[test]
+fn threshold_calibration() {
+    let scalar = |value: f64| FaceEncoding::new_from_scalar(value);
+    let (a, b, c, d, e) = (
+        scalar(0.0),
+        scalar(0.01),
+        scalar(0.03),
+        scalar(0.1),
+        scalar(0.2),
+    );
+
+    let pairs = [
+        (&a, &b, true),
+        (&b, &c, true),
+        (&a, &c, true),
+        (&a, &d, false),
+        (&a, &e, false),
+        (&c, &e, false),
+    ];
+    let calibration = Calibration::new(pairs, Metric::Euclidean).unwrap();
+    assert_eq!(calibration.genuine_pairs(), 3);
+    assert_eq!(calibration.impostor_pairs(), 3);
+
+    // the pairs are separated, so no threshold in between makes any mistake
+    let threshold = calibration.threshold_for_far(0.0).unwrap();
+    assert!((threshold - a.distance(&c)).abs() < 1e-12);
+    assert_eq!(calibration.equal_error_rate(), (threshold, 0.0));
+
+    let rates = calibration.rates(Metric::Euclidean.threshold());
+    assert_eq!(rates.false_accept_rate, 0.0);
+    assert_eq!(rates.false_reject_rate, 0.0);
+    assert_eq!(calibration.roc().len(), 6);
+
+    let mut comparer = FaceComparer::default().with_tolerance(threshold);
+    comparer.insert("a".to_string(), a.clone()).unwrap();
+    assert_eq!(comparer.find(&c), Some(0));
+    assert_eq!(comparer.find(&d), None);
+
+    let other = scalar(0.0).with_fingerprint(ModelFingerprint::from_u64(1));
+    let tagged = scalar(0.0).with_fingerprint(ModelFingerprint::from_u64(2));
+    assert!(matches!(
+        Calibration::new([(&other, &tagged, true)], Metric::Euclidean),
+        Err(CalibrationError::FingerprintMismatch(_))
+    ));
+    assert_eq!(
+        Calibration::new([(&a, &b, true)], Metric::Euclidean).unwrap_err(),
+        CalibrationError::NoImpostorPairs
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn svm_classifier_serde_validation() {
+    let classifier = |labels: &[&str], weights: usize, bias: usize| {
+        serde_json::json!({
+            "labels": labels,
+            "weights": vec![0.5; weights],
+            "bias": vec![0.0; bias],
+            "fingerprint": null,
+        })
+    };
+
+    let valid = serde_json::from_value::<SvmClassifier>(classifier(&["a", "b"], 256, 2)).unwrap();
+    assert_eq!(valid.labels(), ["a", "b"]);
+    assert_eq!(
+        serde_json::from_value::<SvmClassifier>(serde_json::to_value(&valid).unwrap()).unwrap(),
+        valid
+    );
+
+    // classifiers that could not have been trained are rejected instead of failing to predict
+    for invalid in [
+        classifier(&[], 0, 0),
+        classifier(&["a", "b"], 255, 2),
+        classifier(&["a", "b"], 256, 1),
+        classifier(&["b", "a"], 256, 2),
+    ] {
+        assert!(serde_json::from_value::<SvmClassifier>(invalid).is_err());
+    }
+}